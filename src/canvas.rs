@@ -0,0 +1,481 @@
+//! Drawing of the scene to an HTML canvas on wasm32, for previews that match the written
+//! SVG without rendering it to an image first.
+//!
+//! The nodes are replayed as canvas calls. Some SVG features have no exact equivalent: the
+//! opacity of a group applies to each of its children instead of the group as a whole,
+//! gradients always pad, the gradients of strokes ignore their transform and units, and
+//! patterns are not painted at all.
+use std::collections::HashMap;
+
+use js_sys::Array;
+use usvg::tiny_skia_path::{Path as PathData, PathSegment};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{CanvasGradient, CanvasRenderingContext2d, CanvasWindingRule, HtmlImageElement};
+
+use crate::scene::{
+    Align, AspectRatio, BlendMode, Fill, FillRule, GroupNode, ImageKind, ImageNode, ImageRendering,
+    LineCap, LineJoin, Node, Paint, PathNode, Rect, Stop, Stroke, Style, Transform, Units,
+};
+use crate::web::js_error;
+use crate::xml::{blend_mode, hex, image_data_uri};
+use crate::{Document, FontProvider, LyonTranslationError, LyonWriter, NoText};
+
+impl LyonWriter<NoText> {
+    /// Draw the scene to a canvas as [`write`](Self::write) would write it, scaled to fit
+    /// the canvas while keeping its aspect ratio. Text will NOT be drawn!
+    ///
+    /// It waits for the images to be decoded before drawing anything.
+    pub async fn draw_to_canvas(
+        self,
+        ctx: &CanvasRenderingContext2d,
+    ) -> Result<(), LyonTranslationError> {
+        self.prepare(None)?.draw(ctx).await
+    }
+}
+
+impl<T: FontProvider> LyonWriter<Option<T>> {
+    /// Draw the scene to a canvas as [`write`](Self::write) would write it, with the text
+    /// converted to paths.
+    pub async fn draw_to_canvas(
+        self,
+        ctx: &CanvasRenderingContext2d,
+    ) -> Result<(), LyonTranslationError> {
+        self.prepare_text()?.draw(ctx).await
+    }
+}
+
+impl Document {
+    async fn draw(&self, ctx: &CanvasRenderingContext2d) -> Result<(), LyonTranslationError> {
+        let mut images = HashMap::new();
+        for kind in image_kinds(&self.root) {
+            let data = image_data(kind);
+            if images.contains_key(&data) {
+                continue;
+            }
+            let image = HtmlImageElement::new().map_err(|e| js_error("error creating <img>", e))?;
+            image.set_src(&image_data_uri(kind));
+            JsFuture::from(image.decode())
+                .await
+                .map_err(|e| js_error("error decoding image", e))?;
+            images.insert(data, image);
+        }
+        let painter = Painter {
+            ctx,
+            styles: &self.styles,
+            images,
+            alpha: 1.0,
+        };
+        painter
+            .draw_document(self)
+            .map_err(|e| js_error("error drawing to canvas", e))
+    }
+}
+
+/// The images of the scene, in order of appearance.
+fn image_kinds(node: &Node) -> Vec<&ImageKind> {
+    match node {
+        Node::Image(image) => vec![&image.kind],
+        Node::Group(group) => group.children.iter().flat_map(image_kinds).collect(),
+        Node::Path(_) | Node::Text(_) => Vec::new(),
+    }
+}
+
+/// Key of the encoded data of an image, shared by its copies.
+fn image_data(kind: &ImageKind) -> *const Vec<u8> {
+    std::sync::Arc::as_ptr(kind.data())
+}
+
+struct Painter<'a> {
+    ctx: &'a CanvasRenderingContext2d,
+    styles: &'a [(String, Style)],
+    /// Decoded images, by their data.
+    images: HashMap<*const Vec<u8>, HtmlImageElement>,
+    /// Opacity inherited from the groups being drawn.
+    alpha: f64,
+}
+
+impl Painter<'_> {
+    fn draw_document(mut self, document: &Document) -> Result<(), JsValue> {
+        let view_box = document.view_box;
+        let (width, height) = match self.ctx.canvas() {
+            Some(canvas) => (canvas.width() as f64, canvas.height() as f64),
+            None => (view_box.width() as f64, view_box.height() as f64),
+        };
+        // as `preserveAspectRatio="xMidYMid meet"`
+        let scale = (width / view_box.width() as f64).min(height / view_box.height() as f64);
+        self.ctx.save();
+        self.ctx.translate(
+            (width - view_box.width() as f64 * scale) / 2.0,
+            (height - view_box.height() as f64 * scale) / 2.0,
+        )?;
+        self.ctx.scale(scale, scale)?;
+        self.ctx
+            .translate(-view_box.x() as f64, -view_box.y() as f64)?;
+        let result = self.draw_node(&document.root);
+        self.ctx.restore();
+        result
+    }
+
+    fn draw_node(&mut self, node: &Node) -> Result<(), JsValue> {
+        match node {
+            Node::Path(path) => self.draw_path(path),
+            Node::Image(image) => self.draw_image(image),
+            Node::Group(group) => self.draw_group(group),
+            // text must be converted to paths before drawing
+            Node::Text(_) => Ok(()),
+        }
+    }
+
+    fn draw_group(&mut self, group: &GroupNode) -> Result<(), JsValue> {
+        if !group.visible {
+            return Ok(());
+        }
+        self.ctx.save();
+        let alpha = self.alpha;
+        self.alpha *= group.opacity.clamp(0.0, 1.0) as f64;
+        let result = self.apply_transform(group.transform).and_then(|_| {
+            if group.blend_mode != BlendMode::Normal {
+                self.ctx
+                    .set_global_composite_operation(blend_mode(group.blend_mode))?;
+            }
+            group
+                .children
+                .iter()
+                .try_for_each(|child| self.draw_node(child))
+        });
+        self.alpha = alpha;
+        self.ctx.restore();
+        result
+    }
+
+    fn draw_path(&mut self, path: &PathNode) -> Result<(), JsValue> {
+        let (fill, stroke) = match path.style {
+            Some(ref class) => match self.styles.iter().find(|(name, _)| name == class) {
+                Some((_, style)) => (style.fill.clone(), style.stroke.clone()),
+                // a class without rules, drawn with the default SVG fill
+                None => (Some(Fill::default()), None),
+            },
+            None => (path.fill.clone(), path.stroke.clone()),
+        };
+        self.ctx.save();
+        let result = self.apply_transform(path.transform).and_then(|_| {
+            self.trace(&path.data);
+            if let Some(ref fill) = fill {
+                self.fill(fill, &path.data)?;
+            }
+            if let Some(ref stroke) = stroke {
+                self.stroke(stroke)?;
+            }
+            Ok(())
+        });
+        self.ctx.restore();
+        result
+    }
+
+    fn draw_image(&mut self, image: &ImageNode) -> Result<(), JsValue> {
+        let Some(element) = self.images.get(&image_data(&image.kind)) else {
+            return Ok(());
+        };
+        self.ctx.save();
+        self.ctx.set_global_alpha(self.alpha);
+        if image.rendering_mode != ImageRendering::OptimizeQuality {
+            self.ctx.set_image_smoothing_enabled(false);
+        }
+        let rect = image.rect;
+        let result = self.apply_transform(image.transform).and_then(|_| {
+            let (x, y, width, height) = fit(
+                rect,
+                element.natural_width() as f32,
+                element.natural_height() as f32,
+                image.aspect,
+            );
+            if image.aspect.slice {
+                self.ctx.begin_path();
+                self.ctx.rect(
+                    rect.x() as f64,
+                    rect.y() as f64,
+                    rect.width() as f64,
+                    rect.height() as f64,
+                );
+                self.ctx.clip();
+            }
+            self.ctx.draw_image_with_html_image_element_and_dw_and_dh(
+                element,
+                x as f64,
+                y as f64,
+                width as f64,
+                height as f64,
+            )
+        });
+        self.ctx.restore();
+        result
+    }
+
+    /// Set the current path of the canvas to `data`.
+    fn trace(&self, data: &PathData) {
+        self.ctx.begin_path();
+        for segment in data.segments() {
+            match segment {
+                PathSegment::MoveTo(p) => self.ctx.move_to(p.x as f64, p.y as f64),
+                PathSegment::LineTo(p) => self.ctx.line_to(p.x as f64, p.y as f64),
+                PathSegment::QuadTo(p1, p) => {
+                    self.ctx
+                        .quadratic_curve_to(p1.x as f64, p1.y as f64, p.x as f64, p.y as f64)
+                }
+                PathSegment::CubicTo(p1, p2, p) => self.ctx.bezier_curve_to(
+                    p1.x as f64,
+                    p1.y as f64,
+                    p2.x as f64,
+                    p2.y as f64,
+                    p.x as f64,
+                    p.y as f64,
+                ),
+                PathSegment::Close => self.ctx.close_path(),
+            }
+        }
+    }
+
+    fn fill(&self, fill: &Fill, data: &PathData) -> Result<(), JsValue> {
+        self.ctx.save();
+        self.ctx
+            .set_global_alpha(self.alpha * fill.opacity.clamp(0.0, 1.0) as f64);
+        // the current path is already fixed, so transforming now only moves the gradient
+        let result = match fill.paint {
+            Paint::Color(color) => {
+                self.ctx.set_fill_style_str(&hex(color));
+                Ok(())
+            }
+            Paint::LinearGradient(ref lg) => {
+                self.gradient_space(lg.units, lg.transform, data).map(|_| {
+                    let gradient = self.ctx.create_linear_gradient(
+                        lg.x1 as f64,
+                        lg.y1 as f64,
+                        lg.x2 as f64,
+                        lg.y2 as f64,
+                    );
+                    add_stops(&gradient, &lg.stops);
+                    self.ctx.set_fill_style_canvas_gradient(&gradient);
+                })
+            }
+            Paint::RadialGradient(ref rg) => self
+                .gradient_space(rg.units, rg.transform, data)
+                .and_then(|_| {
+                    let gradient = self.ctx.create_radial_gradient(
+                        rg.fx as f64,
+                        rg.fy as f64,
+                        0.0,
+                        rg.cx as f64,
+                        rg.cy as f64,
+                        rg.r as f64,
+                    )?;
+                    add_stops(&gradient, &rg.stops);
+                    self.ctx.set_fill_style_canvas_gradient(&gradient);
+                    Ok(())
+                }),
+            Paint::Pattern(_) => {
+                self.ctx.set_fill_style_str("transparent");
+                Ok(())
+            }
+        }
+        .map(|_| match fill.rule {
+            FillRule::NonZero => self.ctx.fill(),
+            FillRule::EvenOdd => self
+                .ctx
+                .fill_with_canvas_winding_rule(CanvasWindingRule::Evenodd),
+        });
+        self.ctx.restore();
+        result
+    }
+
+    fn stroke(&self, stroke: &Stroke) -> Result<(), JsValue> {
+        self.ctx
+            .set_global_alpha(self.alpha * stroke.opacity.clamp(0.0, 1.0) as f64);
+        self.ctx.set_line_width(stroke.width as f64);
+        self.ctx.set_line_cap(match stroke.linecap {
+            LineCap::Butt => "butt",
+            LineCap::Round => "round",
+            LineCap::Square => "square",
+        });
+        self.ctx.set_line_join(match stroke.linejoin {
+            LineJoin::Miter | LineJoin::MiterClip => "miter",
+            LineJoin::Round => "round",
+            LineJoin::Bevel => "bevel",
+        });
+        self.ctx.set_miter_limit(stroke.miterlimit.max(1.0) as f64);
+        let dashes: Array = stroke
+            .dasharray
+            .iter()
+            .flatten()
+            .map(|dash| JsValue::from_f64(*dash as f64))
+            .collect();
+        self.ctx.set_line_dash(&dashes)?;
+        self.ctx.set_line_dash_offset(stroke.dashoffset as f64);
+        match stroke.paint {
+            Paint::Color(color) => self.ctx.set_stroke_style_str(&hex(color)),
+            Paint::LinearGradient(ref lg) => {
+                let gradient = self.ctx.create_linear_gradient(
+                    lg.x1 as f64,
+                    lg.y1 as f64,
+                    lg.x2 as f64,
+                    lg.y2 as f64,
+                );
+                add_stops(&gradient, &lg.stops);
+                self.ctx.set_stroke_style_canvas_gradient(&gradient);
+            }
+            Paint::RadialGradient(ref rg) => {
+                let gradient = self.ctx.create_radial_gradient(
+                    rg.fx as f64,
+                    rg.fy as f64,
+                    0.0,
+                    rg.cx as f64,
+                    rg.cy as f64,
+                    rg.r as f64,
+                )?;
+                add_stops(&gradient, &rg.stops);
+                self.ctx.set_stroke_style_canvas_gradient(&gradient);
+            }
+            Paint::Pattern(_) => self.ctx.set_stroke_style_str("transparent"),
+        }
+        self.ctx.stroke();
+        Ok(())
+    }
+
+    /// Move to the coordinate system of a gradient of a path with `data`.
+    fn gradient_space(
+        &self,
+        units: Units,
+        transform: Transform,
+        data: &PathData,
+    ) -> Result<(), JsValue> {
+        if units == Units::ObjectBoundingBox {
+            let bounds = data.bounds();
+            self.ctx.transform(
+                bounds.width() as f64,
+                0.0,
+                0.0,
+                bounds.height() as f64,
+                bounds.left() as f64,
+                bounds.top() as f64,
+            )?;
+        }
+        self.apply_transform(transform)
+    }
+
+    fn apply_transform(&self, ts: Transform) -> Result<(), JsValue> {
+        if ts.is_identity() {
+            return Ok(());
+        }
+        self.ctx.transform(
+            ts.sx as f64,
+            ts.ky as f64,
+            ts.kx as f64,
+            ts.sy as f64,
+            ts.tx as f64,
+            ts.ty as f64,
+        )
+    }
+}
+
+/// Where an image of `width` x `height` pixels is drawn to be fitted in `rect`, as
+/// `preserveAspectRatio` would.
+fn fit(rect: Rect, width: f32, height: f32, aspect: AspectRatio) -> (f32, f32, f32, f32) {
+    let stretched = (rect.x(), rect.y(), rect.width(), rect.height());
+    // SVG documents without a size of their own have no natural size
+    let Some((fx, fy)) = align_factors(aspect.align).filter(|_| width > 0.0 && height > 0.0) else {
+        return stretched;
+    };
+    let (sx, sy) = (rect.width() / width, rect.height() / height);
+    let scale = if aspect.slice { sx.max(sy) } else { sx.min(sy) };
+    let (width, height) = (width * scale, height * scale);
+    (
+        rect.x() + (rect.width() - width) * fx,
+        rect.y() + (rect.height() - height) * fy,
+        width,
+        height,
+    )
+}
+
+/// The position of an image along each axis of its rect: 0 at the start, 1 at the end,
+/// or `None` when it is stretched.
+fn align_factors(align: Align) -> Option<(f32, f32)> {
+    Some(match align {
+        Align::None => return None,
+        Align::XMinYMin => (0.0, 0.0),
+        Align::XMidYMin => (0.5, 0.0),
+        Align::XMaxYMin => (1.0, 0.0),
+        Align::XMinYMid => (0.0, 0.5),
+        Align::XMidYMid => (0.5, 0.5),
+        Align::XMaxYMid => (1.0, 0.5),
+        Align::XMinYMax => (0.0, 1.0),
+        Align::XMidYMax => (0.5, 1.0),
+        Align::XMaxYMax => (1.0, 1.0),
+    })
+}
+
+fn add_stops(gradient: &CanvasGradient, stops: &[Stop]) {
+    for stop in stops {
+        let color = format!(
+            "rgba({}, {}, {}, {})",
+            stop.color.red,
+            stop.color.green,
+            stop.color.blue,
+            stop.opacity.clamp(0.0, 1.0)
+        );
+        // only fails for offsets out of [0, 1] or invalid colors, which are not written
+        let _ = gradient.add_color_stop(stop.offset.clamp(0.0, 1.0), &color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lyon_path::geom::euclid::Point2D;
+    use lyon_path::Path;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+    use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+    use crate::{fill, Color, LyonWriter};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    async fn paths_are_drawn_to_fit() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let canvas: HtmlCanvasElement = document
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+        canvas.set_width(20);
+        canvas.set_height(10);
+        let ctx: CanvasRenderingContext2d = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+
+        // a 1x1 square, drawn as a 10x10 one centered in the canvas
+        let mut writer = LyonWriter::new();
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::new(0.0, 0.0));
+        path_builder.line_to(Point2D::new(1.0, 0.0));
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.line_to(Point2D::new(0.0, 1.0));
+        path_builder.end(true);
+        writer
+            .push(
+                &path_builder.build(),
+                Some(fill(Color::new_rgb(255, 0, 0), 1.0)),
+                None,
+                None,
+            )
+            .unwrap();
+        writer.draw_to_canvas(&ctx).await.unwrap();
+
+        let pixel = |x: f64, y: f64| ctx.get_image_data(x, y, 1.0, 1.0).unwrap().data().0;
+        assert_eq!(pixel(10.0, 5.0), [255, 0, 0, 255]);
+        assert_eq!(pixel(2.0, 5.0), [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Scopes to configure the groups built with [`LyonWriter::group`] and [`LyonWriter::layer`].
+use std::ops::{Deref, DerefMut};
+
+use crate::{BlendMode, GroupNode, LyonWriter, NodeId, SvgTransform};
+
+/// A group being built with [`LyonWriter::group`].
+///
+/// It dereferences to the [`LyonWriter`], so everything pushed through it goes into the
+/// group. The setters return the scope so that they can be chained.
+pub struct GroupScope<'a, T> {
+    writer: &'a mut LyonWriter<T>,
+    id: NodeId,
+}
+
+impl<'a, T> GroupScope<'a, T> {
+    pub(crate) fn new(writer: &'a mut LyonWriter<T>, id: NodeId) -> Self {
+        Self { writer, id }
+    }
+
+    /// The handle of the group, to edit it once built.
+    pub fn node_id(&self) -> NodeId {
+        self.id
+    }
+
+    fn node(&mut self) -> &mut GroupNode {
+        self.writer.group_node_mut(self.id)
+    }
+
+    /// Set the `id` of the `<g>` element.
+    pub fn id(&mut self, id: impl Into<String>) -> &mut Self {
+        self.node().attributes.id = id.into();
+        self
+    }
+
+    /// Add a CSS class to the `<g>` element.
+    pub fn class(&mut self, class: impl Into<String>) -> &mut Self {
+        self.node().attributes.classes.push(class.into());
+        self
+    }
+
+    /// Add a `data-{name}` attribute to the `<g>` element.
+    pub fn data(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        let (name, value) = (name.into(), value.into());
+        self.node().attributes.data.push((name, value));
+        self
+    }
+
+    /// Set the tooltip of the group, written as a `<title>`.
+    pub fn title(&mut self, title: impl Into<String>) -> &mut Self {
+        self.node().attributes.title = Some(title.into());
+        self
+    }
+
+    /// Set the description of the group, written as a `<desc>`.
+    pub fn desc(&mut self, desc: impl Into<String>) -> &mut Self {
+        self.node().attributes.desc = Some(desc.into());
+        self
+    }
+
+    /// Wrap the group in a link to `href`.
+    pub fn href(&mut self, href: impl Into<String>) -> &mut Self {
+        self.node().attributes.href = Some(href.into());
+        self
+    }
+
+    /// Set the [`SvgTransform`] applied to all the children of the group.
+    pub fn transform(&mut self, transform: SvgTransform) -> &mut Self {
+        self.node().transform = transform;
+        refresh_bbox(self.writer, self.id);
+        self
+    }
+
+    /// Set the opacity of the group as a whole, clamped between 0 and 1.
+    pub fn opacity(&mut self, opacity: f32) -> &mut Self {
+        self.node().opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set how the group is blended with what is drawn below it.
+    pub fn blend_mode(&mut self, blend_mode: BlendMode) -> &mut Self {
+        self.node().blend_mode = blend_mode;
+        self
+    }
+
+    /// Isolate the group, so that the blending of its children stops at it.
+    pub fn isolate(&mut self, isolate: bool) -> &mut Self {
+        self.node().isolate = isolate;
+        self
+    }
+}
+
+/// Drop the extent of the children of the group `id` from the bounding box of the writer
+/// once they moved, when they were already pushed or the group is reopened.
+fn refresh_bbox<T>(writer: &mut LyonWriter<T>, id: NodeId) {
+    if !writer.children(id).is_empty() {
+        writer.invalidate_bbox();
+    }
+}
+
+impl<T> Deref for GroupScope<'_, T> {
+    type Target = LyonWriter<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.writer
+    }
+}
+
+impl<T> DerefMut for GroupScope<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.writer
+    }
+}
+
+/// A layer opened with [`LyonWriter::layer`], closed when dropped.
+///
+/// Like [`GroupScope`], it dereferences to the [`LyonWriter`] so that everything pushed
+/// through it goes into the layer.
+pub struct LayerScope<'a, T> {
+    writer: &'a mut LyonWriter<T>,
+    id: NodeId,
+}
+
+impl<'a, T> LayerScope<'a, T> {
+    pub(crate) fn new(writer: &'a mut LyonWriter<T>, id: NodeId) -> Self {
+        writer.open_groups.push(id);
+        Self { writer, id }
+    }
+
+    /// The handle of the layer group.
+    pub fn node_id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Show or hide the layer, it is still written but with `display:none`.
+    pub fn visible(&mut self, visible: bool) -> &mut Self {
+        self.writer.group_node_mut(self.id).visible = visible;
+        self
+    }
+
+    /// Set the [`SvgTransform`] applied to all the content of the layer.
+    pub fn transform(&mut self, transform: SvgTransform) -> &mut Self {
+        self.writer.group_node_mut(self.id).transform = transform;
+        refresh_bbox(self.writer, self.id);
+        self
+    }
+
+    /// Set the opacity of the layer as a whole, clamped between 0 and 1.
+    pub fn opacity(&mut self, opacity: f32) -> &mut Self {
+        self.writer.group_node_mut(self.id).opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl<T> Deref for LayerScope<'_, T> {
+    type Target = LyonWriter<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.writer
+    }
+}
+
+impl<T> DerefMut for LayerScope<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.writer
+    }
+}
+
+impl<T> Drop for LayerScope<'_, T> {
+    fn drop(&mut self) {
+        self.writer.close_group(self.id);
+    }
+}
@@ -1,61 +1,165 @@
 use crate::LyonTranslationError;
+use std::borrow::Cow;
 use std::path::Path;
-use usvg::{TreeWriting, XmlOptions};
 
-/// Write to file, WASM aware.
+/// How [`LyonWriter::write`](crate::LyonWriter::write) handles the file, see
+/// [`LyonWriter::with_file_options`](crate::LyonWriter::with_file_options).
+///
+/// They do not apply to WASM, where the file is downloaded instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileOptions {
+    /// Write to a temporary file in the same directory and rename it into place once
+    /// complete, so that the file is never seen half-written.
+    pub atomic: bool,
+    /// Create the missing parent directories of the file.
+    pub create_dirs: bool,
+    /// Replace the file if it exists, otherwise fail with an [`AlreadyExists`] error.
+    ///
+    /// [`AlreadyExists`]: std::io::ErrorKind::AlreadyExists
+    pub overwrite: bool,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            atomic: false,
+            create_dirs: false,
+            overwrite: true,
+        }
+    }
+}
+
+/// Write to file, WASM aware: on wasm32, the browser downloads it instead.
+///
+/// The SVG is gzip-compressed if the extension of `file_path` is `svgz`.
 ///
 /// WASM part adapted from [bevyengine/bevy#8455](/bevyengine/bevy/pull/8455).
-pub fn to_file<P: AsRef<Path>>(tree: usvg::Tree, file_path: P) -> Result<(), LyonTranslationError> {
-    // simply write string to path
+pub fn to_file<P: AsRef<Path>>(
+    svg: &str,
+    file_path: P,
+    options: FileOptions,
+) -> Result<(), LyonTranslationError> {
+    let data = if is_svgz(file_path.as_ref()) {
+        Cow::Owned(gzip(svg)?)
+    } else {
+        Cow::Borrowed(svg.as_bytes())
+    };
     #[cfg(not(target_arch = "wasm32"))]
-    {
-        use std::io::Write;
-        let mut output = std::fs::File::create::<P>(file_path)
-            .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))?;
-        write!(output, "{}", tree.to_string(&XmlOptions::default()))
-            .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))?;
-    }
+    write_file(&data, file_path.as_ref(), options)
+        .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))?;
 
     #[cfg(target_arch = "wasm32")]
     {
-        #[derive(Debug)]
-        struct WASMError(&'static str);
-
-        match (|| {
-            let file_path = file_path.as_ref().to_owned();
-            use wasm_bindgen::{JsCast, JsValue};
-            let svg = tree.to_string(&XmlOptions::default());
-            let blob = web_sys::Blob::new_with_str_sequence(&js_sys::Array::from_iter(
-                std::iter::once(JsValue::from_str(svg.as_str())),
-            ))
-            .map_err(|_| WASMError("error writing blob"))?;
-            let url = web_sys::Url::create_object_url_with_blob(&blob)
-                .map_err(|_| WASMError("error writing url"))?;
-            let window = web_sys::window().unwrap();
-            let document = window.document().unwrap();
-            let link = document
-                .create_element("a")
-                .map_err(|_| WASMError("error creating <a>"))?;
-            link.set_attribute("href", &url)
-                .map_err(|_| WASMError("error creating <href>"))?;
-            link.set_attribute(
-                "download",
-                file_path
-                    .file_name()
-                    .and_then(|filename| filename.to_str())
-                    .ok_or_else(|| WASMError("Invalid filename"))?,
-            )
-            .map_err(|_| WASMError("Invalid filename"))?;
-            let html_element = link
-                .dyn_into::<web_sys::HtmlElement>()
-                .map_err(|_| WASMError("error creating <html>"))?;
-            html_element.click();
-            web_sys::Url::revoke_object_url(&url).map_err(|_| WASMError("Error revoking url"))?;
-            Ok::<(), WASMError>(())
-        })() {
-            Err(e) => return Err(LyonTranslationError::IoWrite(format!("{:?}", e).into())),
-            _ => (),
-        };
+        let _ = options;
+        let file_name = file_path
+            .as_ref()
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .ok_or_else(|| LyonTranslationError::IoWrite("Invalid filename".into()))?;
+        crate::web::download(&data, file_name)?;
     }
     Ok(())
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_file(data: &[u8], file_path: &Path, options: FileOptions) -> std::io::Result<()> {
+    use std::fs;
+    use std::io::Write;
+
+    if options.create_dirs {
+        if let Some(dir) = file_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+    }
+    if !options.atomic {
+        let mut output = if options.overwrite {
+            fs::File::create(file_path)?
+        } else {
+            fs::File::options()
+                .write(true)
+                .create_new(true)
+                .open(file_path)?
+        };
+        return output.write_all(data);
+    }
+    let temp_path = temp_path(file_path);
+    let result = fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .and_then(|mut temp| {
+            temp.write_all(data)?;
+            temp.sync_all()
+        })
+        .and_then(|_| move_into_place(&temp_path, file_path, options.overwrite));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// A hidden file next to `file_path`, unique to this process and call.
+#[cfg(not(target_arch = "wasm32"))]
+fn temp_path(file_path: &Path) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    file_path.with_file_name(format!(
+        ".{name}.{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Rename the complete `temp_path` to `file_path`.
+///
+/// Without `overwrite`, the file is hard linked instead, which fails if `file_path` exists
+/// even if it appeared meanwhile. Where hard links are not supported, it falls back to
+/// checking that it does not exist before renaming.
+#[cfg(not(target_arch = "wasm32"))]
+fn move_into_place(temp_path: &Path, file_path: &Path, overwrite: bool) -> std::io::Result<()> {
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+
+    if overwrite {
+        return fs::rename(temp_path, file_path);
+    }
+    match fs::hard_link(temp_path, file_path) {
+        Ok(()) => fs::remove_file(temp_path),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(e),
+        Err(_) if file_path.exists() => Err(Error::from(ErrorKind::AlreadyExists)),
+        Err(_) => fs::rename(temp_path, file_path),
+    }
+}
+
+fn is_svgz(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svgz"))
+}
+
+/// Compress a SVG with gzip, as a `.svgz` file.
+pub(crate) fn gzip(svg: &str) -> Result<Vec<u8>, LyonTranslationError> {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(svg.as_bytes())
+        .and_then(|_| encoder.finish())
+        .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))
+}
+
+/// Encode a SVG as a `data:image/svg+xml;base64,...` URI.
+pub(crate) fn data_uri(svg: &str) -> String {
+    use base64::Engine;
+
+    format!(
+        "data:image/svg+xml;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(svg)
+    )
+}
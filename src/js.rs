@@ -0,0 +1,373 @@
+//! JavaScript API on wasm32, to build SVGs with the same writer from the front-end.
+//!
+//! Paths are given as SVG path data, colors as CSS colors and transforms as SVG
+//! `transform` attributes, so that they can be taken as they are from the DOM:
+//!
+//! ```js
+//! const writer = new SvgWriter();
+//! writer.pushPath("M 0 0 L 10 0 L 10 10 Z", "#fd4d2c", "black", 0.5);
+//! writer.pushCommands(new Float32Array([0, 0, 0, 1, 5, 5]), undefined, "blue", 1, "scale(2)");
+//! const svg = writer.toSvg();
+//! ```
+use lyon_path::math::{point, Point};
+use lyon_path::Path;
+use svgtypes::SimplePathSegment;
+use wasm_bindgen::prelude::*;
+
+use crate::{Color, DominantBaseline, LyonTranslationError, LyonWriter, SvgTransform};
+
+/// Codes of the commands of [`JsWriter::push_commands`], followed by their coordinates.
+const MOVE_TO: f32 = 0.0;
+const LINE_TO: f32 = 1.0;
+const QUAD_TO: f32 = 2.0;
+const CUBIC_TO: f32 = 3.0;
+const CLOSE: f32 = 4.0;
+
+/// A [`LyonWriter`] exported to JavaScript as `SvgWriter`.
+///
+/// Text can be pushed once fonts are added with `addFont`, and is converted to paths.
+#[wasm_bindgen(js_name = SvgWriter)]
+pub struct JsWriter {
+    writer: LyonWriter<Option<usvg::fontdb::Database>>,
+}
+
+#[wasm_bindgen(js_class = SvgWriter)]
+impl JsWriter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> JsWriter {
+        console_error_panic_hook::set_once();
+        JsWriter {
+            writer: LyonWriter::new().add_fonts(usvg::fontdb::Database::new()),
+        }
+    }
+
+    /// Load a font file (TTF, OTF, TTC...) to lay out the text.
+    #[wasm_bindgen(js_name = addFont)]
+    pub fn add_font(&mut self, data: Vec<u8>) {
+        self.writer
+            .fontdb
+            .get_or_insert_with(usvg::fontdb::Database::new)
+            .load_font_data(data);
+    }
+
+    /// Push a path from SVG path data, like the `d` attribute of a `<path>`.
+    ///
+    /// `fill` and `stroke` are CSS colors, the path is not filled or stroked if missing.
+    #[wasm_bindgen(js_name = pushPath)]
+    pub fn push_path(
+        &mut self,
+        d: &str,
+        fill: Option<String>,
+        stroke: Option<String>,
+        stroke_width: Option<f32>,
+        transform: Option<String>,
+    ) -> Result<(), JsError> {
+        let path = parse_path(d)?;
+        self.push(&path, fill, stroke, stroke_width, transform)
+    }
+
+    /// Push a path from a flat array of commands, each a code followed by its coordinates:
+    /// `0, x, y` to move, `1, x, y` for a line, `2, x1, y1, x, y` for a quadratic curve,
+    /// `3, x1, y1, x2, y2, x, y` for a cubic curve and `4` to close the subpath.
+    #[wasm_bindgen(js_name = pushCommands)]
+    pub fn push_commands(
+        &mut self,
+        commands: &[f32],
+        fill: Option<String>,
+        stroke: Option<String>,
+        stroke_width: Option<f32>,
+        transform: Option<String>,
+    ) -> Result<(), JsError> {
+        let path = path_from_commands(commands)?;
+        self.push(&path, fill, stroke, stroke_width, transform)
+    }
+
+    /// Push text, with `font_families` as in the CSS `font-family` property.
+    ///
+    /// It is written as paths, so the fonts must be added beforehand with `addFont`.
+    #[wasm_bindgen(js_name = pushText)]
+    pub fn push_text(
+        &mut self,
+        text: String,
+        font_families: &str,
+        font_size: f32,
+        fill: Option<String>,
+        transform: Option<String>,
+    ) -> Result<(), JsError> {
+        let font_families = font_families
+            .split(',')
+            .map(|family| family.trim().trim_matches(['"', '\'']).to_string())
+            .filter(|family| !family.is_empty())
+            .collect();
+        let fill =
+            parse_color(fill.as_deref())?.map(|(color, opacity)| crate::fill(color, opacity));
+        self.writer
+            .push_text(
+                text,
+                font_families,
+                font_size,
+                parse_transform(transform.as_deref())?.unwrap_or_default(),
+                fill,
+                None,
+                DominantBaseline::Auto,
+            )
+            .map_err(js_error)?;
+        Ok(())
+    }
+
+    /// Write the SVG to a string, which consumes the writer.
+    #[wasm_bindgen(js_name = toSvg)]
+    pub fn to_svg(self) -> Result<String, JsError> {
+        self.writer.to_svg_string().map_err(js_error)
+    }
+
+    fn push(
+        &mut self,
+        path: &Path,
+        fill: Option<String>,
+        stroke: Option<String>,
+        stroke_width: Option<f32>,
+        transform: Option<String>,
+    ) -> Result<(), JsError> {
+        let fill =
+            parse_color(fill.as_deref())?.map(|(color, opacity)| crate::fill(color, opacity));
+        let stroke_width = stroke_width.unwrap_or(1.0);
+        if !(stroke_width > 0.0 && stroke_width.is_finite()) {
+            return Err(JsError::new(&format!(
+                "invalid stroke width {stroke_width}, it must be positive"
+            )));
+        }
+        let stroke = parse_color(stroke.as_deref())?
+            .map(|(color, opacity)| crate::stroke(color, opacity, stroke_width));
+        self.writer
+            .push(path, fill, stroke, parse_transform(transform.as_deref())?)
+            .map_err(js_error)?;
+        Ok(())
+    }
+}
+
+impl Default for JsWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn js_error(e: LyonTranslationError) -> JsError {
+    JsError::new(&format!("{e:?}"))
+}
+
+/// A CSS color and its opacity.
+fn parse_color(color: Option<&str>) -> Result<Option<(Color, f32)>, JsError> {
+    let Some(color) = color else {
+        return Ok(None);
+    };
+    let parsed: svgtypes::Color = color
+        .parse()
+        .map_err(|e| JsError::new(&format!("invalid color `{color}`: {e}")))?;
+    Ok(Some((
+        Color::new_rgb(parsed.red, parsed.green, parsed.blue),
+        parsed.alpha as f32 / 255.0,
+    )))
+}
+
+fn parse_transform(transform: Option<&str>) -> Result<Option<SvgTransform>, JsError> {
+    let Some(transform) = transform else {
+        return Ok(None);
+    };
+    let ts: svgtypes::Transform = transform
+        .parse()
+        .map_err(|e| JsError::new(&format!("invalid transform `{transform}`: {e}")))?;
+    Ok(Some(SvgTransform::from_row(
+        ts.a as f32,
+        ts.b as f32,
+        ts.c as f32,
+        ts.d as f32,
+        ts.e as f32,
+        ts.f as f32,
+    )))
+}
+
+/// Build a [`Path`] from SVG path data, with arcs and shorthands converted to curves.
+fn parse_path(d: &str) -> Result<Path, JsError> {
+    let mut builder = SubpathBuilder::default();
+    for segment in svgtypes::SimplifyingPathParser::from(d) {
+        let segment = segment.map_err(|e| JsError::new(&format!("invalid path data: {e}")))?;
+        match segment {
+            SimplePathSegment::MoveTo { x, y } => builder.move_to(point(x as f32, y as f32)),
+            SimplePathSegment::LineTo { x, y } => builder.line_to(point(x as f32, y as f32)),
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                builder.quadratic_to(point(x1 as f32, y1 as f32), point(x as f32, y as f32))
+            }
+            SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => builder.cubic_to(
+                point(x1 as f32, y1 as f32),
+                point(x2 as f32, y2 as f32),
+                point(x as f32, y as f32),
+            ),
+            SimplePathSegment::ClosePath => builder.close(),
+        }
+    }
+    Ok(builder.build())
+}
+
+/// Build a [`Path`] from the flat commands of [`JsWriter::push_commands`].
+fn path_from_commands(commands: &[f32]) -> Result<Path, JsError> {
+    let mut builder = SubpathBuilder::default();
+    let mut rest = commands;
+    while let Some((&code, args)) = rest.split_first() {
+        let arity = match code {
+            MOVE_TO | LINE_TO => 2,
+            QUAD_TO => 4,
+            CUBIC_TO => 6,
+            CLOSE => 0,
+            _ => return Err(JsError::new(&format!("unknown path command {code}"))),
+        };
+        if args.len() < arity {
+            return Err(JsError::new(&format!(
+                "path command {code} needs {arity} coordinates"
+            )));
+        }
+        let p = |i: usize| point(args[i], args[i + 1]);
+        match code {
+            MOVE_TO => builder.move_to(p(0)),
+            LINE_TO => builder.line_to(p(0)),
+            QUAD_TO => builder.quadratic_to(p(0), p(2)),
+            CUBIC_TO => builder.cubic_to(p(0), p(2), p(4)),
+            _ => builder.close(),
+        }
+        rest = &args[arity..];
+    }
+    Ok(builder.build())
+}
+
+/// Wraps the lyon builder, which needs each subpath to be explicitly begun and ended.
+#[derive(Default)]
+struct SubpathBuilder {
+    builder: lyon_path::path::Builder,
+    /// The subpath being built, if any.
+    open: bool,
+    /// Where a drawing command without a previous move starts from.
+    current: Point,
+    /// Start of the subpath being built, where `current` goes back to once it is closed.
+    start: Point,
+}
+
+impl SubpathBuilder {
+    fn move_to(&mut self, to: Point) {
+        if self.open {
+            self.builder.end(false);
+        }
+        self.builder.begin(to);
+        self.open = true;
+        self.current = to;
+        self.start = to;
+    }
+
+    fn ensure_open(&mut self) {
+        if !self.open {
+            self.move_to(self.current);
+        }
+    }
+
+    fn line_to(&mut self, to: Point) {
+        self.ensure_open();
+        self.builder.line_to(to);
+        self.current = to;
+    }
+
+    fn quadratic_to(&mut self, ctrl: Point, to: Point) {
+        self.ensure_open();
+        self.builder.quadratic_bezier_to(ctrl, to);
+        self.current = to;
+    }
+
+    fn cubic_to(&mut self, ctrl1: Point, ctrl2: Point, to: Point) {
+        self.ensure_open();
+        self.builder.cubic_bezier_to(ctrl1, ctrl2, to);
+        self.current = to;
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.builder.end(true);
+            self.open = false;
+            self.current = self.start;
+        }
+    }
+
+    fn build(mut self) -> Path {
+        if self.open {
+            self.builder.end(false);
+        }
+        self.builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lyon_path::Event;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::{path_from_commands, JsWriter};
+
+    #[wasm_bindgen_test]
+    fn paths_are_pushed_from_js() {
+        let mut writer = JsWriter::new();
+        writer
+            .push_path(
+                "M 0 0 h 10 v 10 z",
+                Some("rgb(255, 0, 0)".to_string()),
+                None,
+                None,
+                Some("translate(1, 2)".to_string()),
+            )
+            .unwrap();
+        writer
+            .push_commands(
+                &[0.0, 0.0, 0.0, 1.0, 5.0, 5.0],
+                None,
+                Some("blue".to_string()),
+                Some(2.0),
+                None,
+            )
+            .unwrap();
+        let svg = writer.to_svg().unwrap();
+        assert!(svg.contains(r##"fill="#ff0000""##));
+        assert!(svg.contains(r#"transform="matrix(1 0 0 1 1 2)""#));
+        assert!(svg.contains(r##"stroke="#0000ff""##));
+        assert!(svg.contains(r#"stroke-width="2""#));
+    }
+
+    #[wasm_bindgen_test]
+    fn invalid_stroke_widths_are_errors() {
+        let mut writer = JsWriter::new();
+        for width in [0.0, -1.0, f32::NAN] {
+            let blue = Some("blue".to_string());
+            assert!(writer
+                .push_path("M 0 0 L 1 1", None, blue, Some(width), None)
+                .is_err());
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn commands_after_close_start_from_the_subpath() {
+        let commands = [
+            0.0, 1.0, 1.0, 1.0, 4.0, 1.0, 1.0, 4.0, 4.0, 4.0, 1.0, 0.0, 4.0,
+        ];
+        let path = path_from_commands(&commands).unwrap();
+        let begins: Vec<_> = path
+            .iter()
+            .filter_map(|event| match event {
+                Event::Begin { at } => Some((at.x, at.y)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(begins, [(1.0, 1.0), (1.0, 1.0)]);
+    }
+}
@@ -1,22 +1,55 @@
 //! *roarsvg* is a library to deserialize lyon [`Path`]s into SVG.
 //!
 //! It provides a struct [`LyonWriter`] that accepts a [`push`](LyonWriter::push) operation to append [`Path`]s
-//! and a [`write`](LyonWriter::write) operation to write all those paths to an SVG using [`usvg`].
-use std::rc::Rc;
+//! and a [`write`](LyonWriter::write) operation to write all those paths to an SVG.
+//!
+//! The scene is described with roarsvg's own types ([`Node`], [`Fill`], [`Stroke`],
+//! [`SvgTransform`]...), [`usvg`] is only used internally to lay out text.
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
 
 use lyon_path::{Event, Path};
 
 use usvg::fontdb::Source;
 use usvg::tiny_skia_path::{Path as PathData, PathBuilder};
 use usvg::{
-    AlignmentBaseline, AspectRatio, CharacterPosition, Font, Group, ImageRendering, LengthAdjust,
-    NodeExt, NonZeroPositiveF32, NonZeroRect, Opacity, Paint, PaintOrder, Path as SvgPath, Size,
-    TextAnchor, TextChunk, TextRendering, TextSpan, TreeTextToPath, ViewBox, WritingMode,
+    AlignmentBaseline, CharacterPosition, Font, LengthAdjust, NonZeroPositiveF32, NonZeroRect,
+    PaintOrder, Size, TextAnchor, TextChunk, TextRendering, TextSpan, TreeTextToPath, ViewBox,
+    WritingMode,
 };
-pub use usvg::{Color, DominantBaseline, Fill, NodeKind, Stroke, Transform as SvgTransform};
-use usvg::{StrokeWidth, Text, Tree};
+use usvg::{Text, Tree};
+#[cfg(target_arch = "wasm32")]
+mod canvas;
+mod group;
 mod io;
-use io::to_file;
+#[cfg(target_arch = "wasm32")]
+mod js;
+mod nodes;
+mod raster;
+mod scene;
+mod snapshot;
+mod stream;
+#[cfg(target_arch = "wasm32")]
+mod web;
+mod xml;
+pub use group::{GroupScope, LayerScope};
+pub use io::FileOptions;
+use io::{data_uri, gzip, to_file};
+#[cfg(target_arch = "wasm32")]
+pub use js::JsWriter;
+use nodes::Arena;
+pub use nodes::{NodeId, SortPolicy};
+use scene::{check_finite, check_name, check_names};
+pub use scene::{
+    Align, AspectRatio, Attributes, BlendMode, Color, DominantBaseline, Fill, FillRule, GroupNode,
+    ImageFormat, ImageKind, ImageNode, ImageRendering, LineCap, LineJoin, LinearGradient, Metadata,
+    Node, Paint, PathNode, Pattern, RadialGradient, Rect, SpreadMethod, Stop, Stroke, Style,
+    TextNode, Transform as SvgTransform, Units,
+};
+pub use snapshot::assert_snapshot;
+pub use stream::LyonStreamWriter;
+pub use xml::{AttributeQuotes, Indent, WriteOptions};
 
 #[derive(Debug)]
 pub enum LyonTranslationError {
@@ -30,11 +63,48 @@ pub enum LyonTranslationError {
     SvgFailure,
     FontFailure,
     IoWrite(Box<dyn std::error::Error>),
+    UnknownSymbol(SymbolId),
+    /// The node was removed.
+    UnknownNode(NodeId),
+    /// The group is still being built by a [`GroupScope`] or a [`LayerScope`], so it
+    /// cannot be removed.
+    OpenGroup(NodeId),
+    /// The operation does not apply to this kind of node, like setting the fill of an image
+    /// or of an instance of a symbol.
+    WrongNodeKind(NodeId),
+    /// The pixels of a raster image do not match its size, 4 bytes per pixel for RGBA.
+    WrongImageData {
+        width: u32,
+        height: u32,
+        len: usize,
+    },
+    /// The format of the image data could not be detected, see [`ImageFormat::detect`].
+    UnknownImageFormat,
+    /// An id, a CSS class or the name of a `data-*` attribute that would break the SVG,
+    /// see [`Attributes`].
+    InvalidName(String),
+    /// An id of the form of the ones generated by the writer, like `layer1`, that could
+    /// collide with them.
+    ReservedId(String),
+    /// A coordinate, length, opacity or transform is NaN or infinite, which SVG cannot
+    /// represent.
+    NotFinite(f32),
 }
 
-/// Translate from [`lyon_path::Path`] to [`usvg::Path`] on [`push`](Self::push)
+/// A [`Path`] with the fill, stroke and transform it is [pushed](LyonWriter::push) with.
+pub type StyledPath = (Path, Option<Fill>, Option<Stroke>, Option<SvgTransform>);
+
+/// Handle to a shape registered with [`LyonWriter::define_symbol`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolId(usize);
+
+/// Translate from [`lyon_path::Path`] to [`PathNode`] on [`push`](Self::push)
 /// and [write](Self::write) an SVG to a file.
 ///
+/// The writer owns everything pushed to it without reference counting, so it is
+/// [`Send`] and [`Sync`]: it can be filled on a worker thread, or several writers can be
+/// filled in parallel and then merged with [`append`](Self::append).
+///
 /// # Example
 ///
 /// ```
@@ -73,17 +143,36 @@ pub enum LyonTranslationError {
 /// # std::fs::remove_file(&file_path).unwrap();
 /// ```
 pub struct LyonWriter<T> {
-    nodes: Vec<usvg::Node>,
+    arena: Arena,
+    symbols: Vec<PathNode>,
+    /// Path data pushed so far, by hash, so that identical geometry is shared.
+    geometries: HashMap<u64, Vec<Arc<PathData>>>,
+    /// Bounding box of the nodes after the global transform. It grows with the pushes and
+    /// is emptied by the edits that may shrink it, to be computed again when needed.
+    bbox: OnceLock<Option<Rect>>,
     global_transform: Option<SvgTransform>,
+    /// Groups being built by [`group`](Self::group), innermost last.
+    open_groups: Vec<NodeId>,
+    /// Labels of the layers and their groups, in order of creation.
+    layers: Vec<(String, NodeId)>,
+    /// Only these layers are written, if set.
+    exported_layers: Option<HashSet<String>>,
+    sort_policy: SortPolicy,
+    /// Styles written to the stylesheet, by class, in order of definition.
+    styles: Vec<(String, Style)>,
+    metadata: Metadata,
+    write_options: WriteOptions,
+    file_options: FileOptions,
     fontdb: T,
 }
 
 /// Utility function to build a [`Stroke`].
 pub fn stroke(color: Color, opacity: f32, width: f32) -> Stroke {
+    assert!(width > 0.0 && width.is_finite(), "Put a real width...");
     Stroke {
         paint: Paint::Color(color),
-        opacity: Opacity::new_clamped(opacity),
-        width: StrokeWidth::new(width).expect("Put a real width..."),
+        opacity: opacity.clamp(0.0, 1.0),
+        width,
         ..Default::default()
     }
 }
@@ -92,46 +181,524 @@ pub fn stroke(color: Color, opacity: f32, width: f32) -> Stroke {
 pub fn fill(color: Color, opacity: f32) -> Fill {
     Fill {
         paint: Paint::Color(color),
-        opacity: Opacity::new_clamped(opacity),
+        opacity: opacity.clamp(0.0, 1.0),
         ..Default::default()
     }
 }
 
 impl<T> LyonWriter<T> {
     /// Add a [`Path`] to the writer and translate it (eager).
+    ///
+    /// Geometry identical to a previously pushed path is shared in memory and
+    /// written once, referenced by `<use>` elements.
+    ///
+    /// The returned [`NodeId`] can be used to edit the path afterwards, like giving it an id
+    /// and CSS classes with [`set_attributes`](Self::set_attributes).
     pub fn push(
         &mut self,
         path: &Path,
         fill: Option<Fill>,
         stroke: Option<Stroke>,
         transform: Option<SvgTransform>,
-    ) -> Result<(), LyonTranslationError> {
-        self.nodes.push(usvg::Node::new(NodeKind::Path(
-            lyon_path_to_svg_with_attributes(path, fill, stroke, transform)
-                .ok_or(LyonTranslationError::SvgFailure)?,
-        )));
-        Ok(())
+    ) -> Result<NodeId, LyonTranslationError> {
+        let transform = transform.unwrap_or_default();
+        let converted = ConvertedPath::new(path, transform, self.global_transform)
+            .ok_or(LyonTranslationError::SvgFailure)?;
+        Ok(self.push_converted(converted, fill, stroke, transform))
+    }
+
+    /// Add many [`Path`]s at once, in the same order as calling [`push`](Self::push) on each.
+    ///
+    /// With the `rayon` feature, the paths are translated in parallel. Nothing is added
+    /// if any of them fails to translate.
+    pub fn push_many(&mut self, paths: &[StyledPath]) -> Result<Vec<NodeId>, LyonTranslationError> {
+        let global_transform = self.global_transform;
+        let convert = |(path, _, _, transform): &StyledPath| {
+            ConvertedPath::new(path, transform.unwrap_or_default(), global_transform)
+        };
+        #[cfg(feature = "rayon")]
+        let converted: Option<Vec<ConvertedPath>> = {
+            use rayon::prelude::*;
+            paths.par_iter().map(convert).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let converted: Option<Vec<ConvertedPath>> = paths.iter().map(convert).collect();
+        let converted = converted.ok_or(LyonTranslationError::SvgFailure)?;
+        Ok(converted
+            .into_iter()
+            .zip(paths)
+            .map(|(converted, (_, fill, stroke, transform))| {
+                self.push_converted(
+                    converted,
+                    fill.clone(),
+                    stroke.clone(),
+                    transform.unwrap_or_default(),
+                )
+            })
+            .collect())
+    }
+
+    fn push_converted(
+        &mut self,
+        converted: ConvertedPath,
+        fill: Option<Fill>,
+        stroke: Option<Stroke>,
+        transform: SvgTransform,
+    ) -> NodeId {
+        let data = self.share_geometry(converted.data, converted.hash);
+        if let (Some(bbox), true) = (converted.bbox, self.open_groups.is_empty()) {
+            self.grow_bbox(bbox);
+        }
+        self.arena.insert(
+            Node::Path(PathNode {
+                attributes: Attributes::default(),
+                data,
+                fill,
+                stroke,
+                transform,
+                style: None,
+            }),
+            self.open_groups.last().copied(),
+        )
+    }
+
+    /// Append a node, growing the bounding box to contain it.
+    ///
+    /// Inside of a [`group`](Self::group), the bounding box grows when the outermost
+    /// group is closed, once all the transforms above the node are known.
+    fn push_scene_node(&mut self, node: Node) -> NodeId {
+        self.push_scene_node_in(node, self.open_groups.last().copied())
+    }
+
+    fn push_scene_node_in(&mut self, node: Node, parent: Option<NodeId>) -> NodeId {
+        if parent.is_none() {
+            let global_transform = self.global_transform.unwrap_or_default();
+            if let Some(bbox) = node_bbox(&node, global_transform) {
+                self.grow_bbox(bbox);
+            }
+        }
+        self.arena.insert(node, parent)
+    }
+
+    /// Stop pushing into the open group `id`.
+    ///
+    /// Top level groups grow the bounding box with everything pushed into them.
+    pub(crate) fn close_group(&mut self, id: NodeId) {
+        let closed = self.open_groups.pop();
+        debug_assert_eq!(closed, Some(id), "groups are closed in reverse order");
+        if self.parent(id).is_none() {
+            if let Some(bbox) = self.node_bbox(id) {
+                self.grow_bbox(bbox);
+            }
+        }
+    }
+
+    /// Build a group with `build`: everything pushed inside of it becomes a child of the group.
+    ///
+    /// The [`GroupScope`] sets the transform, opacity, blending and id of the group and can
+    /// open nested groups in turn. The result of `build` is passed through, the [`NodeId`]
+    /// of the group is given by [`GroupScope::node_id`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use roarsvg::{BlendMode, Color, LyonWriter, SvgTransform, fill};
+    /// use lyon_path::Path;
+    /// use lyon_path::geom::euclid::Point2D;
+    ///
+    /// let file_path = "groups.svg";
+    /// let mut writer = LyonWriter::new();
+    ///
+    /// let mut path_builder = Path::builder();
+    /// path_builder.begin(Point2D::origin());
+    /// path_builder.line_to(Point2D::new(1.0, 0.0));
+    /// path_builder.line_to(Point2D::new(0.5, 1.0));
+    /// path_builder.end(true);
+    /// let triangle = path_builder.build();
+    /// writer
+    ///     .group(|g| {
+    ///         g.id("body").transform(SvgTransform::from_translate(2.0, 0.0));
+    ///         g.push(&triangle, Some(fill(Color::black(), 1.0)), None, None)?;
+    ///         g.group(|arm| {
+    ///             arm.opacity(0.5).blend_mode(BlendMode::Multiply).isolate(true);
+    ///             arm.push(
+    ///                 &triangle,
+    ///                 Some(fill(Color::new_rgb(253, 77, 44), 1.0)),
+    ///                 None,
+    ///                 Some(SvgTransform::from_rotate(45.0)),
+    ///             )
+    ///         })
+    ///     })
+    ///     .expect("Paths should be writable!");
+    /// writer.write(file_path).expect("Writing should not panic!");
+    ///
+    /// # std::fs::remove_file(&file_path).unwrap();
+    /// ```
+    pub fn group<R>(&mut self, build: impl FnOnce(&mut GroupScope<T>) -> R) -> R {
+        let id = self.push_scene_node(Node::Group(GroupNode::default()));
+        self.open_groups.push(id);
+        let result = build(&mut GroupScope::new(self, id));
+        self.close_group(id);
+        result
+    }
+
+    /// Push into the layer `label` until the returned [`LayerScope`] is dropped.
+    ///
+    /// A layer is a top level group written with the `inkscape:groupmode` and
+    /// `inkscape:label` attributes, so that it is listed as a layer by Inkscape and
+    /// Illustrator. The layer is created the first time its label is used and reopened
+    /// afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use roarsvg::{Color, LyonWriter, stroke};
+    /// use lyon_path::Path;
+    /// use lyon_path::geom::euclid::Point2D;
+    ///
+    /// let file_path = "layers.svg";
+    /// let mut writer = LyonWriter::new();
+    ///
+    /// let mut path_builder = Path::builder();
+    /// path_builder.begin(Point2D::origin());
+    /// path_builder.line_to(Point2D::new(1.0, 1.0));
+    /// path_builder.end(false);
+    /// let path = path_builder.build();
+    /// writer
+    ///     .layer("Drawing")
+    ///     .push(&path, None, Some(stroke(Color::black(), 1.0, 0.1)), None)
+    ///     .expect("Path should be writable!");
+    /// {
+    ///     let mut annotations = writer.layer("Annotations");
+    ///     annotations.visible(false);
+    ///     annotations
+    ///         .push(&path, None, Some(stroke(Color::new_rgb(255, 0, 0), 1.0, 0.1)), None)
+    ///         .expect("Path should be writable!");
+    /// }
+    /// writer.write(file_path).expect("Writing should not panic!");
+    ///
+    /// # std::fs::remove_file(&file_path).unwrap();
+    /// ```
+    pub fn layer(&mut self, label: &str) -> LayerScope<'_, T> {
+        let existing = self
+            .layers
+            .iter()
+            .find(|(name, id)| name == label && self.arena.get(*id).is_some())
+            .map(|(_, id)| *id);
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = self.push_scene_node_in(
+                    Node::Group(GroupNode {
+                        attributes: Attributes::new()
+                            .with_id(format!("layer{}", self.layers.len() + 1)),
+                        layer: Some(label.to_string()),
+                        ..Default::default()
+                    }),
+                    None,
+                );
+                self.layers.push((label.to_string(), id));
+                id
+            }
+        };
+        LayerScope::new(self, id)
+    }
+
+    /// Only write the layers whose label is in `labels`, content outside of layers is kept.
+    pub fn with_exported_layers<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exported_layers = Some(labels.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Return the already pushed [`PathData`] equal to `data`, if any, or start sharing it.
+    fn share_geometry(&mut self, data: PathData, hash: u64) -> Arc<PathData> {
+        let bucket = self.geometries.entry(hash).or_default();
+        if let Some(shared) = bucket.iter().find(|shared| ***shared == data) {
+            return shared.clone();
+        }
+        let shared = Arc::new(data);
+        bucket.push(shared.clone());
+        shared
+    }
+
+    /// Register a [`Path`] with its fill and stroke to be drawn many times with
+    /// [`push_instance`](Self::push_instance).
+    ///
+    /// The path is written once as a `<symbol>` and each instance as a `<use>` referencing it,
+    /// so neither memory nor file size grow with the vertex count times the instance count.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use roarsvg::{Color, LyonWriter, SvgTransform, fill};
+    /// use lyon_path::Path;
+    /// use lyon_path::geom::euclid::Point2D;
+    ///
+    /// let file_path = "symbols.svg";
+    /// let mut writer = LyonWriter::new();
+    ///
+    /// let mut path_builder = Path::builder();
+    /// path_builder.begin(Point2D::origin());
+    /// path_builder.line_to(Point2D::new(1.0, 0.0));
+    /// path_builder.line_to(Point2D::new(0.5, 1.0));
+    /// path_builder.end(true);
+    /// let marker = writer
+    ///     .define_symbol(&path_builder.build(), Some(fill(Color::black(), 1.0)), None)
+    ///     .expect("Marker should be writable!");
+    /// for i in 0..100 {
+    ///     let (x, y) = (i as f32, (i as f32).sin());
+    ///     writer
+    ///         .push_instance(marker, SvgTransform::from_translate(x, y))
+    ///         .expect("Marker was defined in this writer!");
+    /// }
+    /// writer.write(file_path).expect("Writing should not panic!");
+    ///
+    /// # std::fs::remove_file(&file_path).unwrap();
+    /// ```
+    pub fn define_symbol(
+        &mut self,
+        path: &Path,
+        fill: Option<Fill>,
+        stroke: Option<Stroke>,
+    ) -> Result<SymbolId, LyonTranslationError> {
+        self.symbols
+            .push(lyon_path_to_svg_with_attributes(path, fill, stroke, None)?);
+        Ok(SymbolId(self.symbols.len() - 1))
+    }
+
+    /// Add a [`Style`] to the stylesheet of the SVG as the CSS class `class`, or replace
+    /// the one already there.
+    ///
+    /// Paths [pushed](Self::push_styled) with the class are drawn with the style, so that
+    /// the whole figure can be restyled by only redefining it, like for a dark theme.
+    /// `class` must be a valid name, as described in [`Attributes`], or writing fails with
+    /// [`LyonTranslationError::InvalidName`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use roarsvg::{Color, LyonWriter, Style, fill, stroke};
+    /// use lyon_path::Path;
+    /// use lyon_path::geom::euclid::Point2D;
+    ///
+    /// let file_path = "style.svg";
+    /// let mut writer = LyonWriter::new();
+    /// let mut path_builder = Path::builder();
+    /// path_builder.begin(Point2D::origin());
+    /// path_builder.line_to(Point2D::new(1.0, 1.0));
+    /// path_builder.line_to(Point2D::new(0.0, 1.0));
+    /// path_builder.end(true);
+    /// let path = path_builder.build();
+    /// writer.define_style(
+    ///     "shape",
+    ///     Style {
+    ///         fill: Some(fill(Color::white(), 1.0)),
+    ///         stroke: Some(stroke(Color::black(), 1.0, 0.1)),
+    ///     },
+    /// );
+    /// writer
+    ///     .push_styled(&path, "shape", None)
+    ///     .expect("Path should be writable!");
+    /// // dark theme
+    /// writer.define_style(
+    ///     "shape",
+    ///     Style {
+    ///         fill: Some(fill(Color::black(), 1.0)),
+    ///         stroke: Some(stroke(Color::white(), 1.0, 0.1)),
+    ///     },
+    /// );
+    /// writer.write(file_path).expect("Writing should not panic!");
+    ///
+    /// # std::fs::remove_file(&file_path).unwrap();
+    /// ```
+    pub fn define_style(&mut self, class: impl Into<String>, style: Style) {
+        let class = class.into();
+        match self.styles.iter_mut().find(|(name, _)| *name == class) {
+            Some((_, existing)) => *existing = style,
+            None => self.styles.push((class, style)),
+        }
+    }
+
+    /// The [`Style`] defined for `class`, if any.
+    pub fn style(&self, class: &str) -> Option<&Style> {
+        self.styles
+            .iter()
+            .find(|(name, _)| name == class)
+            .map(|(_, style)| style)
+    }
+
+    /// Add a [`Path`] drawn with the [`Style`] of `class` instead of its own fill and stroke.
+    ///
+    /// The style may be [defined](Self::define_style) before or after the push.
+    pub fn push_styled(
+        &mut self,
+        path: &Path,
+        class: &str,
+        transform: Option<SvgTransform>,
+    ) -> Result<NodeId, LyonTranslationError> {
+        check_name(class)?;
+        let id = self.push(path, None, None, transform)?;
+        self.set_style(id, Some(class.to_string()))?;
+        Ok(id)
+    }
+
+    /// Draw a symbol registered with [`define_symbol`](Self::define_symbol) at `transform`.
+    ///
+    /// The instance shares the path data of its symbol instead of copying it.
+    pub fn push_instance(
+        &mut self,
+        symbol: SymbolId,
+        transform: SvgTransform,
+    ) -> Result<NodeId, LyonTranslationError> {
+        let mut instance = self
+            .symbols
+            .get(symbol.0)
+            .ok_or(LyonTranslationError::UnknownSymbol(symbol))?
+            .clone();
+        instance.transform = transform;
+        Ok(self.push_scene_node(Node::Path(instance)))
     }
 
-    /// Push a node kind without any indirection.
+    /// Push a node without any indirection.
     ///
     /// For writing Text, call first [`Self::add_fonts`] and call `push_text` instead.
-    pub fn push_node(&mut self, node: NodeKind) {
-        self.nodes.push(usvg::Node::new(node));
+    /// The children of a [`GroupNode`] get their own [`NodeId`]s, see
+    /// [`children`](Self::children).
+    pub fn push_node(&mut self, node: Node) -> NodeId {
+        self.push_scene_node(node)
     }
 
-    /// Push a raster image (formatted by the caller) as a PNG.
+    /// Push a raster image (formatted by the caller) as a PNG, see [`create_png_node`].
+    ///
+    /// Its rendering and aspect ratio can be changed with
+    /// [`set_image_rendering`](Self::set_image_rendering) and
+    /// [`set_aspect_ratio`](Self::set_aspect_ratio).
     pub fn push_png(
         &mut self,
         data: &[u8],
         transform: SvgTransform,
         width: f32,
         height: f32,
-    ) -> Result<(), LyonTranslationError> {
-        self.nodes.push(usvg::Node::new(create_png_node(
-            data, transform, width, height,
-        )?));
-        Ok(())
+    ) -> Result<NodeId, LyonTranslationError> {
+        Ok(self.push_node(create_png_node(data, transform, width, height)?))
+    }
+
+    /// Push a raster image from its RGBA pixels, row by row with 8 bits per channel, drawn
+    /// in `placement`. It is encoded as a PNG.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use roarsvg::{LyonWriter, Rect};
+    ///
+    /// // a 2x2 heatmap, stretched to 10x10
+    /// let pixels = [
+    ///     255, 0, 0, 255, 0, 0, 255, 255,
+    ///     0, 0, 255, 255, 255, 0, 0, 255,
+    /// ];
+    /// let mut writer = LyonWriter::new();
+    /// writer
+    ///     .push_rgba(2, 2, &pixels, Rect::from_xywh(0.0, 0.0, 10.0, 10.0).unwrap())
+    ///     .expect("Pixels should match the size!");
+    /// ```
+    pub fn push_rgba(
+        &mut self,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        placement: Rect,
+    ) -> Result<NodeId, LyonTranslationError> {
+        let png = raster::encode_png(width, height, data)?;
+        Ok(self.push_image_kind(ImageKind::Png(Arc::new(png)), placement))
+    }
+
+    /// Push an image of the [`image`] crate, drawn in `placement`. It is encoded as a PNG,
+    /// converted to 8-bit RGBA.
+    #[cfg(feature = "image")]
+    pub fn push_image(
+        &mut self,
+        image: &image::DynamicImage,
+        placement: Rect,
+    ) -> Result<NodeId, LyonTranslationError> {
+        let rgba = image.to_rgba8();
+        self.push_rgba(rgba.width(), rgba.height(), rgba.as_raw(), placement)
+    }
+
+    /// Push an encoded image, drawn in `placement`. Its format is detected from its first
+    /// bytes, see [`ImageFormat::detect`]; the data is embedded as it is, without
+    /// re-encoding.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use roarsvg::{LyonWriter, Rect};
+    ///
+    /// let photo = std::fs::read("photo.jpg").unwrap();
+    /// let mut writer = LyonWriter::new();
+    /// writer
+    ///     .push_encoded_image(&photo, Rect::from_xywh(0.0, 0.0, 400.0, 300.0).unwrap())
+    ///     .expect("JPEG should be detected!");
+    /// ```
+    pub fn push_encoded_image(
+        &mut self,
+        data: &[u8],
+        placement: Rect,
+    ) -> Result<NodeId, LyonTranslationError> {
+        let format = ImageFormat::detect(data).ok_or(LyonTranslationError::UnknownImageFormat)?;
+        self.push_encoded_image_as(data, format, placement)
+    }
+
+    /// Push an image encoded as `format`, drawn in `placement`.
+    pub fn push_encoded_image_as(
+        &mut self,
+        data: &[u8],
+        format: ImageFormat,
+        placement: Rect,
+    ) -> Result<NodeId, LyonTranslationError> {
+        let kind = ImageKind::new(format, Arc::new(data.to_vec()));
+        Ok(self.push_image_kind(kind, placement))
+    }
+
+    /// Push a SVG document as a nested image, drawn in `placement`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use roarsvg::{Color, LyonWriter, Rect, fill};
+    /// use lyon_path::Path;
+    /// use lyon_path::geom::euclid::Point2D;
+    ///
+    /// let mut inset = LyonWriter::new();
+    /// let mut path_builder = Path::builder();
+    /// path_builder.begin(Point2D::origin());
+    /// path_builder.line_to(Point2D::new(3.0, 2.0));
+    /// path_builder.line_to(Point2D::new(0.0, 2.0));
+    /// path_builder.end(true);
+    /// inset
+    ///     .push(&path_builder.build(), Some(fill(Color::black(), 1.0)), None, None)
+    ///     .expect("Path should be writable!");
+    ///
+    /// let mut writer = LyonWriter::new();
+    /// let placement = Rect::from_xywh(10.0, 10.0, 30.0, 20.0).unwrap();
+    /// writer.push_svg(&inset.to_svg_string().unwrap(), placement);
+    /// ```
+    pub fn push_svg(&mut self, svg: &str, placement: Rect) -> NodeId {
+        self.push_image_kind(ImageKind::Svg(Arc::new(svg.into())), placement)
+    }
+
+    fn push_image_kind(&mut self, kind: ImageKind, placement: Rect) -> NodeId {
+        self.push_node(Node::Image(ImageNode {
+            attributes: Attributes::default(),
+            kind,
+            rect: placement,
+            transform: SvgTransform::identity(),
+            rendering_mode: ImageRendering::default(),
+            aspect: AspectRatio::default(),
+        }))
     }
 
     /// Push a vector of nodes as the children of their own group (formatted by the caller).
@@ -139,134 +706,215 @@ impl<T> LyonWriter<T> {
     /// This is relevant for applying transforms to a set of elements.
     pub fn push_group(
         &mut self,
-        nodes: Vec<NodeKind>,
+        nodes: Vec<Node>,
         transform: SvgTransform,
-    ) -> Result<(), LyonTranslationError> {
-        let group_node = usvg::Node::new(NodeKind::Group(Group {
+    ) -> Result<NodeId, LyonTranslationError> {
+        let group_node = Node::Group(GroupNode {
             transform,
+            children: nodes,
             ..Default::default()
-        }));
-        for node in nodes {
-            group_node.append(usvg::Node::new(node))
+        });
+        Ok(self.push_scene_node(group_node))
+    }
+
+    /// Move everything pushed to `other` after what was pushed to `self`.
+    ///
+    /// This is meant for building parts of a scene on different threads. The global
+    /// transform of `other` is applied to its nodes, and its symbols stay linked to them.
+    /// Its styles are added, except for the classes that `self` already defines, and its
+    /// layers are numbered after those of `self`. [`layer`](Self::layer) reopens the first
+    /// layer with a label, so a label used by both writers gets two layers.
+    ///
+    /// The nodes keep their z-index, but the [`NodeId`]s of `other` are not valid in `self`.
+    pub fn append<U>(&mut self, mut other: LyonWriter<U>) {
+        // reuse the already shared geometry
+        for (hash, bucket) in std::mem::take(&mut other.geometries) {
+            for data in bucket {
+                let shared = self.share_geometry((*data).clone(), hash);
+                if !Arc::ptr_eq(&shared, &data) {
+                    for node in other.arena.nodes_mut() {
+                        relink_geometry(std::slice::from_mut(node), &data, &shared);
+                    }
+                }
+            }
         }
-        self.nodes.push(group_node);
-        Ok(())
+        self.symbols.extend(other.symbols);
+        for (class, style) in other.styles {
+            if self.style(&class).is_none() {
+                self.styles.push((class, style));
+            }
+        }
+        let parent = self.open_groups.last().copied();
+        let parent = match other.global_transform {
+            Some(transform) => Some(self.push_scene_node_in(
+                Node::Group(GroupNode {
+                    transform,
+                    ..Default::default()
+                }),
+                parent,
+            )),
+            None => parent,
+        };
+        let new_id = self.arena.append(other.arena, parent);
+        // the layers keep their labels, numbered after the layers of `self`
+        for (i, (label, id)) in other.layers.into_iter().enumerate() {
+            let Some(id) = new_id(id) else { continue };
+            let attributes = self.arena.get_mut(id).expect("just moved").attributes_mut();
+            if attributes.id == format!("layer{}", i + 1) {
+                attributes.id = format!("layer{}", self.layers.len() + 1);
+            }
+            self.layers.push((label, id));
+        }
+        self.invalidate_bbox();
+    }
+
+    /// Add/replace the [`Metadata`] of the document: its title, description and language
+    /// for accessibility, and its provenance.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use roarsvg::{LyonWriter, Metadata};
+    ///
+    /// let writer = LyonWriter::new().with_metadata(Metadata {
+    ///     title: Some("Cell counts".to_string()),
+    ///     desc: Some("Number of cells per well after 24h".to_string()),
+    ///     lang: Some("en".to_string()),
+    ///     creator: Some("Imaging facility".to_string()),
+    ///     license: Some("https://creativecommons.org/licenses/by/4.0/".to_string()),
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Add/replace the [`WriteOptions`] formatting the SVG, like its precision and
+    /// indentation.
+    ///
+    /// ```
+    /// use roarsvg::{LyonWriter, WriteOptions};
+    ///
+    /// // compact, but still one element per line to keep diffs readable
+    /// let writer = LyonWriter::new().with_write_options(WriteOptions {
+    ///     coordinates_precision: 2,
+    ///     relative_paths: true,
+    ///     xml_declaration: true,
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn with_write_options(mut self, options: WriteOptions) -> Self {
+        self.write_options = options;
+        self
+    }
+
+    /// Add/replace the [`FileOptions`] used by [`write`](Self::write), like writing the file
+    /// atomically.
+    ///
+    /// ```
+    /// use roarsvg::{FileOptions, LyonWriter};
+    ///
+    /// // other processes watching `figures/` never see a partial file
+    /// let writer = LyonWriter::new().with_file_options(FileOptions {
+    ///     atomic: true,
+    ///     create_dirs: true,
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn with_file_options(mut self, options: FileOptions) -> Self {
+        self.file_options = options;
+        self
+    }
+
+    /// Add/replace the [`SortPolicy`] used to order the nodes on write.
+    pub fn with_sort_policy(mut self, policy: SortPolicy) -> Self {
+        self.sort_policy = policy;
+        self
     }
 
     /// Add/replace a [`SvgTransform`], which will be applied to the whole SVG as a group.
     pub fn with_transform(mut self, trans: SvgTransform) -> Self {
         self.global_transform = Some(trans);
+        // the bounding box is kept after the global transform, so it has to be redone
+        self.invalidate_bbox();
         self
     }
 
-    /// Build [`Tree`] before writing.
-    fn prepare(self) -> Result<Tree, LyonTranslationError> {
-        // get the global transform to apply to each node's bbox
-        let global_transform = self.global_transform.unwrap_or_default();
-        // calculate transformed dimensions
-        let (min_x, max_x, min_y, max_y) = self
-            .nodes
-            .iter()
-            .filter_map(|node| node.calculate_bbox())
-            .flat_map(|bbox| {
-                // we need to adjust the calculate_bbox coordinates
-                // to account for post_* (global_transform) operations
-                let corners = [
-                    (bbox.left(), bbox.top()),     // top-left
-                    (bbox.right(), bbox.top()),    // top-right
-                    (bbox.left(), bbox.bottom()),  // bottom-left
-                    (bbox.right(), bbox.bottom()), // bottom-right
-                ];
-
-                // transform each corner and expand to individual points
-                corners.into_iter().map(move |(x, y)| {
-                    let mut point = usvg::tiny_skia_path::Point::from((x, y));
-                    global_transform.map_point(&mut point);
-                    point
-                })
-            })
-            .fold(
-                (
-                    f32::INFINITY,
-                    f32::NEG_INFINITY,
-                    f32::INFINITY,
-                    f32::NEG_INFINITY,
-                ),
-                |(mut min_x, mut max_x, mut min_y, mut max_y), point| {
-                    min_x = min_x.min(point.x);
-                    max_x = max_x.max(point.x);
-                    min_y = min_y.min(point.y);
-                    max_y = max_y.max(point.y);
-                    (min_x, max_x, min_y, max_y)
-                },
-            );
+    /// Bounding box of everything pushed so far, after applying the global transform.
+    ///
+    /// Curves are bounded exactly instead of by their control points. Stroke widths are
+    /// not included, nor is [`Text`]: it only has a size once converted to paths on write,
+    /// where the viewBox is grown to fit it.
+    pub fn bbox(&self) -> Option<Rect> {
+        *self
+            .bbox
+            .get_or_init(|| self.arena.bbox(self.global_transform.unwrap_or_default()))
+    }
 
-        // Rest of the function remains the same...
-        let width = if max_x - min_x > 0.0 {
-            max_x - min_x
-        } else {
-            256.0
-        };
-        let height = if max_y - min_y > 0.0 {
-            max_y - min_y
-        } else {
-            256.0
-        };
-        let root_node = usvg::Node::new(NodeKind::Group(Group::default()));
+    /// Compute the size and viewBox and gather the nodes under the global transform.
+    ///
+    /// With a `fontdb`, the [`Text`] nodes are converted to paths first, so that the
+    /// viewBox fits them too.
+    fn prepare(
+        mut self,
+        fontdb: Option<&usvg::fontdb::Database>,
+    ) -> Result<Document, LyonTranslationError> {
+        if let Some(exported) = self.exported_layers.take() {
+            let layers = std::mem::take(&mut self.layers);
+            for (_, id) in layers.iter().filter(|(label, _)| !exported.contains(label)) {
+                // the layer may have been removed already
+                let _ = self.remove(*id);
+            }
+        }
+        let mut bbox = self.bbox();
+        self.arena.sort(self.sort_policy);
         // we append everything to a "real" group node
-        let group_node = usvg::Node::new(NodeKind::Group(Group {
+        let mut group_node = Node::Group(GroupNode {
             transform: self.global_transform.unwrap_or_default(),
+            children: self.arena.into_roots(),
             ..Default::default()
-        }));
+        });
+        if let Some(fontdb) = fontdb {
+            text_to_paths(std::slice::from_mut(&mut group_node), fontdb)?;
+            bbox = node_bbox(&group_node, SvgTransform::identity());
+        }
+        let (min_x, max_x, min_y, max_y) = match bbox {
+            Some(bbox) => (bbox.left(), bbox.right(), bbox.top(), bbox.bottom()),
+            None => (
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+            ),
+        };
 
-        // use std::cmp::Ordering::*;
-        // self.nodes
-        //     .sort_unstable_by(|a, b| match (&*a.borrow(), &*b.borrow()) {
-        //         (NodeKind::Group(_), _) => Greater,
-        //         (_, NodeKind::Group(_)) => Less,
-        //         (NodeKind::Image(_), _) => Greater,
-        //         (_, NodeKind::Image(_)) => Less,
-        //         (NodeKind::Text(_), NodeKind::Path(_)) => Greater,
-        //         (NodeKind::Path(_), NodeKind::Text(_)) => Less,
-        //         (NodeKind::Path(p1), NodeKind::Path(p2)) => (2 * p1.fill.is_some() as u8
-        //             + p1.stroke.is_some() as u8)
-        //             .cmp(&(2 * p2.fill.is_some() as u8 + p2.stroke.is_some() as u8)),
-        //         _ => Equal,
-        //     });
-        for path in self.nodes {
-            group_node.append(path);
+        // the nodes may have been edited directly, without being checked
+        for (class, _) in &self.styles {
+            check_name(class)?;
         }
-        root_node.append(group_node);
-
-        Ok(Tree {
-            size: Size::from_wh(width, height).ok_or(LyonTranslationError::WrongBoundingBox {
-                min_x,
-                max_x,
-                min_y,
-                max_y,
-            })?,
-            view_box: ViewBox {
-                rect: NonZeroRect::from_ltrb(min_x, min_y, max_x, max_y).ok_or(
-                    LyonTranslationError::WrongBoundingBox {
-                        min_x,
-                        max_x,
-                        min_y,
-                        max_y,
-                    },
-                )?,
-                aspect: AspectRatio::default(),
-            },
-            root: root_node,
+        check_names(&group_node)?;
+        check_finite(&group_node)?;
+        Ok(Document {
+            view_box: Rect::from_ltrb(min_x, min_y, max_x, max_y)
+                .filter(|rect| rect.width() > 0.0 && rect.height() > 0.0)
+                .ok_or(LyonTranslationError::WrongBoundingBox {
+                    min_x,
+                    max_x,
+                    min_y,
+                    max_y,
+                })?,
+            root: group_node,
+            symbols: self.symbols,
+            styles: self.styles,
+            metadata: self.metadata,
+            options: self.write_options,
         })
     }
 
     /// Loads fonts from a font database, enabling writing [`Text`] (`push_text`).
     pub fn add_fonts<Fp: FontProvider>(self, fonts: Fp) -> LyonWriter<Option<Fp>> {
-        LyonWriter {
-            nodes: self.nodes,
-            global_transform: self.global_transform,
-            fontdb: Some(fonts),
-        }
+        self.with_fontdb(Some(fonts))
     }
 
     /// Loads fonts from a font directory, building a [`FontProvider`] and enabling writing text.
@@ -276,15 +924,34 @@ impl<T> LyonWriter<T> {
     ) -> LyonWriter<Option<usvg::fontdb::Database>> {
         let mut fonts = usvg::fontdb::Database::new();
         fonts.load_fonts_dir(font_dir);
+        self.with_fontdb(Some(fonts))
+    }
+
+    /// Keep everything pushed so far, swapping the font provider.
+    fn with_fontdb<F>(self, fontdb: F) -> LyonWriter<F> {
         LyonWriter {
-            nodes: self.nodes,
+            arena: self.arena,
+            symbols: self.symbols,
+            geometries: self.geometries,
+            bbox: self.bbox,
             global_transform: self.global_transform,
-            fontdb: Some(fonts),
+            open_groups: self.open_groups,
+            layers: self.layers,
+            exported_layers: self.exported_layers,
+            sort_policy: self.sort_policy,
+            styles: self.styles,
+            metadata: self.metadata,
+            write_options: self.write_options,
+            file_options: self.file_options,
+            fontdb,
         }
     }
 }
 
-/// Utility function to create [`usvg::Image`] elements.
+/// Utility function to create [`ImageNode`] elements.
+///
+/// The image is drawn in a `width` x `height` rect at the origin, placed by `transform`,
+/// which may also rotate, scale or skew it.
 ///
 /// If no grouping is needed, [`LyonWriter::push_png`] is recommended instead.
 pub fn create_png_node(
@@ -292,28 +959,25 @@ pub fn create_png_node(
     transform: SvgTransform,
     width: f32,
     height: f32,
-) -> Result<NodeKind, LyonTranslationError> {
-    Ok(NodeKind::Image(usvg::Image {
-        id: "".to_string(),
-        kind: usvg::ImageKind::PNG(std::sync::Arc::new(data.into())),
-        transform: SvgTransform::identity(),
-        visibility: usvg::Visibility::Visible,
-        view_box: ViewBox {
-            rect: NonZeroRect::from_xywh(transform.tx, transform.ty, width, height).ok_or(
-                LyonTranslationError::WrongBoundingBox {
-                    min_x: transform.tx - width / 2.,
-                    max_x: transform.tx + width / 2.,
-                    min_y: transform.ty - height / 2.,
-                    max_y: transform.ty + height / 2.,
-                },
-            )?,
-            aspect: AspectRatio::default(),
-        },
+) -> Result<Node, LyonTranslationError> {
+    Ok(Node::Image(ImageNode {
+        attributes: Attributes::default(),
+        kind: ImageKind::Png(Arc::new(data.into())),
+        transform,
+        rect: Rect::from_xywh(0.0, 0.0, width, height)
+            .filter(|rect| rect.width() > 0.0 && rect.height() > 0.0)
+            .ok_or(LyonTranslationError::WrongBoundingBox {
+                min_x: 0.0,
+                max_x: width,
+                min_y: 0.0,
+                max_y: height,
+            })?,
         rendering_mode: ImageRendering::default(),
+        aspect: AspectRatio::default(),
     }))
 }
 
-/// Utility function to create [`Text`] elements.
+/// Utility function to create [`TextNode`] elements.
 ///
 /// If no grouping is needed, [`LyonWriter::push_text`] is recommended instead.
 pub fn create_text_node(
@@ -324,9 +988,30 @@ pub fn create_text_node(
     font_families: Vec<String>,
     font_size: f32,
     dominant_baseline: DominantBaseline,
-) -> Result<NodeKind, LyonTranslationError> {
-    let text_len = text.len();
-    Ok(NodeKind::Text(Text {
+) -> Result<Node, LyonTranslationError> {
+    if !(font_size > 0.0 && font_size.is_finite()) {
+        return Err(LyonTranslationError::FontFailure);
+    }
+    Ok(Node::Text(TextNode {
+        text,
+        font_families,
+        font_size,
+        transform,
+        fill,
+        stroke,
+        dominant_baseline,
+        attributes: Attributes::default(),
+    }))
+}
+
+/// Build the [`usvg`] [`Text`] of a [`TextNode`], as an unique [`TextChunk`] whose
+/// [`TextSpan`] style applies to all the text.
+///
+/// Only the shape of the glyphs matters for the layout, so the fill and stroke are
+/// placeholders that tell which of them the laid out paths should get.
+fn usvg_text(text: &TextNode) -> Result<Text, LyonTranslationError> {
+    let text_len = text.text.len();
+    Ok(Text {
         id: "".to_string(),
         positions: (0..text_len)
             .map(|c| CharacterPosition {
@@ -337,28 +1022,28 @@ pub fn create_text_node(
             })
             .collect(),
         rotate: Vec::new(),
-        transform,
+        transform: text.transform.to_usvg(),
         rendering_mode: TextRendering::GeometricPrecision,
         writing_mode: WritingMode::LeftToRight,
         chunks: vec![TextChunk {
             x: None,
             y: None,
-            text,
+            text: text.text.clone(),
             anchor: TextAnchor::Start,
             text_flow: usvg::TextFlow::Linear,
             spans: vec![TextSpan {
                 start: 0,
                 end: text_len,
-                fill,
-                stroke,
+                fill: text.fill.as_ref().map(|_| usvg::Fill::default()),
+                stroke: text.stroke.as_ref().map(|_| usvg::Stroke::default()),
                 paint_order: PaintOrder::FillAndStroke,
                 font: Font {
-                    families: font_families,
+                    families: text.font_families.clone(),
                     style: usvg::FontStyle::Normal,
                     stretch: usvg::FontStretch::Normal,
                     weight: 1,
                 },
-                font_size: NonZeroPositiveF32::new(font_size)
+                font_size: NonZeroPositiveF32::new(text.font_size)
                     .ok_or(LyonTranslationError::FontFailure)?,
                 small_caps: false,
                 apply_kerning: false,
@@ -373,35 +1058,89 @@ pub fn create_text_node(
                 text_length: None,
                 length_adjust: LengthAdjust::SpacingAndGlyphs,
                 visibility: usvg::Visibility::Visible,
-                dominant_baseline,
+                dominant_baseline: usvg_baseline(text.dominant_baseline),
                 alignment_baseline: AlignmentBaseline::Auto,
             }],
         }],
-    }))
+    })
+}
+
+fn usvg_baseline(baseline: DominantBaseline) -> usvg::DominantBaseline {
+    match baseline {
+        DominantBaseline::Auto => usvg::DominantBaseline::Auto,
+        DominantBaseline::UseScript => usvg::DominantBaseline::UseScript,
+        DominantBaseline::NoChange => usvg::DominantBaseline::NoChange,
+        DominantBaseline::ResetSize => usvg::DominantBaseline::ResetSize,
+        DominantBaseline::Ideographic => usvg::DominantBaseline::Ideographic,
+        DominantBaseline::Alphabetic => usvg::DominantBaseline::Alphabetic,
+        DominantBaseline::Hanging => usvg::DominantBaseline::Hanging,
+        DominantBaseline::Mathematical => usvg::DominantBaseline::Mathematical,
+        DominantBaseline::Central => usvg::DominantBaseline::Central,
+        DominantBaseline::Middle => usvg::DominantBaseline::Middle,
+        DominantBaseline::TextAfterEdge => usvg::DominantBaseline::TextAfterEdge,
+        DominantBaseline::TextBeforeEdge => usvg::DominantBaseline::TextBeforeEdge,
+    }
 }
+
 /// Marker struct for [`LyonWriter`] that indicates that no [`Text`] node has been added
-/// so far. It disallows `push_text` and does not convert [`Text`] to [`SvgPath`] upon write.
+/// so far. It disallows `push_text` and does not convert [`Text`] to [`usvg::Path`] upon write.
 pub struct NoText;
 
 impl LyonWriter<NoText> {
     pub fn new() -> LyonWriter<NoText> {
         LyonWriter {
-            nodes: Vec::new(),
+            arena: Arena::default(),
+            symbols: Vec::new(),
+            geometries: HashMap::new(),
+            bbox: OnceLock::from(None),
             global_transform: None,
+            open_groups: Vec::new(),
+            layers: Vec::new(),
+            exported_layers: None,
+            sort_policy: SortPolicy::default(),
+            styles: Vec::new(),
+            metadata: Metadata::default(),
+            write_options: WriteOptions::default(),
+            file_options: FileOptions::default(),
             fontdb: NoText,
         }
     }
 
     /// Write the contained [`Path`]s to an SVG at `file_path`. Text will NOT be written!
+    ///
+    /// If the extension of `file_path` is `svgz`, the SVG is compressed with gzip.
+    ///
+    /// The output is reproducible: the same pushes with the same [`WriteOptions`] give a
+    /// byte-identical SVG on every platform, wasm32 included. The ids that roarsvg
+    /// generates (`symbol{n}`, `geometry{n}`, `layer{n}`) are numbered in order of
+    /// appearance, and all ids can be prefixed with [`WriteOptions::id_prefix`].
     pub fn write<P: AsRef<std::path::Path>>(
         self,
         file_path: P,
     ) -> Result<(), LyonTranslationError> {
-        let tree = self.prepare()?;
-        to_file(tree, file_path)?;
+        let file_options = self.file_options;
+        to_file(&self.to_svg_string()?, file_path, file_options)?;
         Ok(())
     }
 
+    /// The SVG that [`write`](Self::write) would write, for instance to compare it to a
+    /// snapshot with [`assert_snapshot`].
+    pub fn to_svg_string(self) -> Result<String, LyonTranslationError> {
+        Ok(self.prepare(None)?.serialize())
+    }
+
+    /// The SVG compressed with gzip, as in a `.svgz` file.
+    ///
+    /// [`write`](Self::write) already compresses it if the file has the `svgz` extension.
+    pub fn to_svgz(self) -> Result<Vec<u8>, LyonTranslationError> {
+        gzip(&self.to_svg_string()?)
+    }
+
+    /// The SVG as a `data:image/svg+xml;base64,...` URI, to embed it in HTML or JSON.
+    pub fn to_data_uri(self) -> Result<String, LyonTranslationError> {
+        Ok(data_uri(&self.to_svg_string()?))
+    }
+
     /// Loads fonts from a font file, building a [`FontProvider`] and enabling writing text.
     pub fn add_fonts_source(
         self,
@@ -409,11 +1148,7 @@ impl LyonWriter<NoText> {
     ) -> LyonWriter<Option<usvg::fontdb::Database>> {
         let mut fonts = usvg::fontdb::Database::new();
         fonts.load_font_source(Source::Binary(font_source));
-        LyonWriter {
-            nodes: self.nodes,
-            global_transform: self.global_transform,
-            fontdb: Some(fonts),
-        }
+        self.with_fontdb(Some(fonts))
     }
 }
 
@@ -454,8 +1189,7 @@ impl<T: FontProvider> LyonWriter<Option<T>> {
     /// let mut fontdb = usvg::fontdb::Database::new();
     /// fontdb.load_system_fonts();
     /// let mut writer = writer.add_fonts(fontdb);
-    /// // first we add a Path, if not, the ViewBox calculation will panic!
-    /// // this is a caveat and should be fixed in the future
+    /// // the viewBox fits both the path and the text
     /// let mut path_builder = Path::builder();
     /// path_builder.begin(Point2D::origin());
     /// path_builder.line_to(
@@ -478,12 +1212,11 @@ impl<T: FontProvider> LyonWriter<Option<T>> {
     ///         vec!["Arial".to_string()],
     ///         12.0,
     ///         SvgTransform::from_translate(0., 0.),
-    ///         Some(fill(usvg::Color::black(), 1.0)),
-    ///         Some(stroke(usvg::Color::black(), 1.0, 1.0)),
+    ///         Some(fill(Color::black(), 1.0)),
+    ///         Some(stroke(Color::black(), 1.0, 1.0)),
     ///         DominantBaseline::Auto,
     ///     )
     ///     .expect("Text should be writable!");
-    /// let mut path_builder = Path::builder();
     /// // finally, write the SVG, Text with be converted to SvgPath
     /// writer.write(file_path).expect("Writing should not panic!");
     ///
@@ -498,8 +1231,8 @@ impl<T: FontProvider> LyonWriter<Option<T>> {
         fill: Option<Fill>,
         stroke: Option<Stroke>,
         dominant_baseline: DominantBaseline,
-    ) -> Result<(), LyonTranslationError> {
-        self.nodes.push(usvg::Node::new(create_text_node(
+    ) -> Result<NodeId, LyonTranslationError> {
+        Ok(self.push_node(create_text_node(
             text,
             transform,
             fill,
@@ -507,40 +1240,129 @@ impl<T: FontProvider> LyonWriter<Option<T>> {
             font_families,
             font_size,
             dominant_baseline,
-        )?));
-        Ok(())
+        )?))
     }
 
     /// Loads fonts from a font file, building a [`FontProvider`] if needed and enabling writing text.
     pub fn add_fonts_source(
-        self,
+        mut self,
         font_source: std::sync::Arc<Vec<u8>>,
     ) -> LyonWriter<Option<usvg::fontdb::Database>> {
-        let mut fonts = self.fontdb.map(|f| f.get_fontdb()).unwrap_or_default();
+        let mut fonts = self
+            .fontdb
+            .take()
+            .map(|f| f.get_fontdb())
+            .unwrap_or_default();
         fonts.load_font_source(Source::Binary(font_source));
-        LyonWriter {
-            nodes: self.nodes,
-            global_transform: self.global_transform,
-            fontdb: Some(fonts),
-        }
+        self.with_fontdb(Some(fonts))
     }
 
-    /// Write the contained [`Path`]s to an SVG at `file_path`, converting all [`Text`] nodes
-    /// to paths.
-    pub fn write<P: AsRef<std::path::Path>>(
-        mut self,
-        file_path: P,
-    ) -> Result<(), LyonTranslationError> {
+    /// Like [`prepare`](Self::prepare), converting all [`Text`] nodes to paths.
+    fn prepare_text(mut self) -> Result<Document, LyonTranslationError> {
         let fontdb = self
             .fontdb
             .take()
             .ok_or(LyonTranslationError::NoFonts)?
             .get_fontdb();
-        let mut tree = self.prepare()?;
-        tree.convert_text(&fontdb);
-        to_file(tree, file_path)?;
+        self.prepare(Some(&fontdb))
+    }
+
+    /// Write the contained [`Path`]s to an SVG at `file_path`, converting all [`Text`] nodes
+    /// to paths.
+    ///
+    /// As without text, the output is reproducible as long as the same fonts are loaded, and
+    /// compressed if the extension of `file_path` is `svgz`.
+    pub fn write<P: AsRef<std::path::Path>>(
+        self,
+        file_path: P,
+    ) -> Result<(), LyonTranslationError> {
+        let file_options = self.file_options;
+        to_file(&self.to_svg_string()?, file_path, file_options)?;
         Ok(())
     }
+
+    /// The SVG that [`write`](Self::write) would write.
+    pub fn to_svg_string(self) -> Result<String, LyonTranslationError> {
+        Ok(self.prepare_text()?.serialize())
+    }
+
+    /// The SVG compressed with gzip, as in a `.svgz` file.
+    pub fn to_svgz(self) -> Result<Vec<u8>, LyonTranslationError> {
+        gzip(&self.to_svg_string()?)
+    }
+
+    /// The SVG as a `data:image/svg+xml;base64,...` URI.
+    pub fn to_data_uri(self) -> Result<String, LyonTranslationError> {
+        Ok(data_uri(&self.to_svg_string()?))
+    }
+}
+
+/// Everything needed to serialize the SVG.
+struct Document {
+    view_box: Rect,
+    root: Node,
+    symbols: Vec<PathNode>,
+    styles: Vec<(String, Style)>,
+    metadata: Metadata,
+    options: WriteOptions,
+}
+
+impl Document {
+    fn serialize(&self) -> String {
+        xml::to_string(
+            self.view_box,
+            std::slice::from_ref(&self.root),
+            &self.symbols,
+            &self.styles,
+            &self.metadata,
+            self.options.clone(),
+        )
+    }
+}
+
+/// Replace the [`Text`] nodes by groups of paths, laying them out with [`usvg`].
+fn text_to_paths(
+    nodes: &mut [Node],
+    fontdb: &usvg::fontdb::Database,
+) -> Result<(), LyonTranslationError> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                let root = usvg::Node::new(usvg::NodeKind::Group(usvg::Group::default()));
+                root.append(usvg::Node::new(usvg::NodeKind::Text(usvg_text(text)?)));
+                let mut tree = Tree {
+                    size: Size::from_wh(1.0, 1.0).expect("1x1 is a valid size"),
+                    view_box: ViewBox {
+                        rect: NonZeroRect::from_xywh(0.0, 0.0, 1.0, 1.0)
+                            .expect("1x1 is a valid rect"),
+                        aspect: usvg::AspectRatio::default(),
+                    },
+                    root,
+                };
+                tree.convert_text(fontdb);
+                let mut glyphs = Node::from_laid_out_text(&tree.root, &text.fill, &text.stroke)
+                    .expect("the root of the tree is a group");
+                if let Node::Group(ref mut group) = glyphs {
+                    group.attributes = text.attributes.clone();
+                }
+                *node = glyphs;
+            }
+            Node::Group(group) => text_to_paths(&mut group.children, fontdb)?,
+            Node::Path(_) | Node::Image(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Point the paths using `from` to the equal geometry `to`.
+fn relink_geometry(nodes: &mut [Node], from: &Arc<PathData>, to: &Arc<PathData>) {
+    for node in nodes {
+        match node {
+            Node::Path(path) if Arc::ptr_eq(&path.data, from) => path.data = to.clone(),
+            Node::Group(group) => relink_geometry(&mut group.children, from, to),
+            _ => {}
+        }
+    }
 }
 
 fn lyon_path_to_svg_with_attributes(
@@ -548,14 +1370,85 @@ fn lyon_path_to_svg_with_attributes(
     fill: Option<Fill>,
     stroke: Option<Stroke>,
     transform: Option<SvgTransform>,
-) -> Option<SvgPath> {
-    let mut op = SvgPath::new(Rc::new(lyon_path_to_usvg(path)?));
-    op.fill = fill;
-    op.stroke = stroke;
-    if let Some(trans) = transform {
-        op.transform = trans;
-    }
-    Some(op)
+) -> Result<PathNode, LyonTranslationError> {
+    PathNode::new(path, fill, stroke, transform.unwrap_or_default())
+}
+
+/// A [`Path`] translated to [`PathData`], which unlike [`usvg::Node`] can cross threads.
+struct ConvertedPath {
+    data: PathData,
+    hash: u64,
+    bbox: Option<Rect>,
+}
+
+impl ConvertedPath {
+    fn new(
+        path: &Path,
+        transform: SvgTransform,
+        global_transform: Option<SvgTransform>,
+    ) -> Option<Self> {
+        let data = lyon_path_to_usvg(path)?;
+        let hash = geometry_hash(&data);
+        let bbox = path_bbox(
+            &data,
+            transform.post_concat(global_transform.unwrap_or_default()),
+        );
+        Some(Self { data, hash, bbox })
+    }
+}
+
+/// Exact bounds of `data` after applying `transform`, curves included.
+fn path_bbox(data: &PathData, transform: SvgTransform) -> Option<Rect> {
+    data.clone()
+        .transform(transform.to_usvg())?
+        .compute_tight_bounds()
+        .map(Rect::from_usvg)
+}
+
+/// Exact bounds of a node after applying `transform` on top of its own.
+fn node_bbox(node: &Node, transform: SvgTransform) -> Option<Rect> {
+    match node {
+        Node::Path(path) => path_bbox(&path.data, path.transform.post_concat(transform)),
+        Node::Image(image) => image
+            .rect
+            .to_usvg()?
+            .transform(image.transform.post_concat(transform).to_usvg())
+            .map(Rect::from_usvg),
+        Node::Group(group) => {
+            let transform = group.transform.post_concat(transform);
+            group
+                .children
+                .iter()
+                .filter_map(|child| node_bbox(child, transform))
+                .fold(None, join_bbox)
+        }
+        Node::Text(_) => None,
+    }
+}
+
+fn join_bbox(bbox: Option<Rect>, other: Rect) -> Option<Rect> {
+    match bbox {
+        None => Some(other),
+        Some(bbox) => Rect::from_ltrb(
+            bbox.left().min(other.left()),
+            bbox.top().min(other.top()),
+            bbox.right().max(other.right()),
+            bbox.bottom().max(other.bottom()),
+        ),
+    }
+}
+
+/// Hash the verbs and the exact coordinates of a path.
+fn geometry_hash(data: &PathData) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for verb in data.verbs() {
+        (*verb as u8).hash(&mut hasher);
+    }
+    for point in data.points() {
+        point.x.to_bits().hash(&mut hasher);
+        point.y.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
 }
 
 fn lyon_path_to_usvg(path: &Path) -> Option<PathData> {
@@ -624,6 +1517,23 @@ mod tests {
 
     use super::*;
 
+    /// An open segment from `(x, 0)` to `(x + 1, 1)`.
+    fn segment(x: f32) -> Path {
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::new(x, 0.0));
+        path_builder.line_to(Point2D::new(x + 1.0, 1.0));
+        path_builder.end(false);
+        path_builder.build()
+    }
+
+    /// Write the SVG to `file_path` and read it back, removing the file.
+    fn written(writer: LyonWriter<NoText>, file_path: &str) -> String {
+        writer.write(file_path).expect("Writing should not panic!");
+        let svg = std::fs::read_to_string(file_path).unwrap();
+        std::fs::remove_file(file_path).unwrap();
+        svg
+    }
+
     #[test]
     fn lines_deserialize() {
         let mut path_builder = Path::builder();
@@ -727,8 +1637,8 @@ mod tests {
                 vec!["Arial".to_string()],
                 12.0,
                 SvgTransform::from_translate(0., 0.),
-                Some(fill(usvg::Color::black(), 1.0)),
-                Some(stroke(usvg::Color::black(), 1.0, 1.0)),
+                Some(fill(Color::black(), 1.0)),
+                Some(stroke(Color::black(), 1.0, 1.0)),
                 DominantBaseline::Auto,
             )
             .expect("Text should be writable!");
@@ -736,4 +1646,1069 @@ mod tests {
         writer.write(file_path).expect("Writing should not panic!");
         std::fs::remove_file(file_path).unwrap();
     }
+
+    #[test]
+    fn streamed_paths_match_the_writer() {
+        let options = WriteOptions {
+            coordinates_precision: 2,
+            indent: Indent::Spaces(2),
+            quotes: AttributeQuotes::Single,
+            xml_declaration: true,
+            ..Default::default()
+        };
+        let mut writer = LyonWriter::new().with_write_options(options.clone());
+        let paths: Vec<Path> = (0..3)
+            .map(|i| {
+                let mut path_builder = Path::builder();
+                path_builder.begin(Point2D::new(i as f32, 0.0));
+                path_builder.line_to(Point2D::new(i as f32 + 1.0 / 3.0, 1.0));
+                path_builder.end(false);
+                path_builder.build()
+            })
+            .collect();
+        for path in &paths {
+            writer
+                .push(path, None, Some(stroke(Color::black(), 1.0, 0.1)), None)
+                .unwrap();
+        }
+        let bbox = writer.bbox().unwrap();
+        let mut streamed = LyonStreamWriter::with_write_options(
+            Vec::new(),
+            bbox.x(),
+            bbox.y(),
+            bbox.width(),
+            bbox.height(),
+            SvgTransform::identity(),
+            options,
+        )
+        .unwrap();
+        for path in &paths {
+            streamed
+                .push(path, None, Some(stroke(Color::black(), 1.0, 0.1)), None)
+                .unwrap();
+        }
+        let streamed = String::from_utf8(streamed.finish().unwrap()).unwrap();
+        // the stream cannot know in advance whether it needs the namespace
+        let streamed = streamed.replace(" xmlns:xlink='http://www.w3.org/1999/xlink'", "");
+        assert_eq!(streamed, writer.to_svg_string().unwrap());
+
+        let mut out = Vec::new();
+        LyonStreamWriter::new(&mut out, 0.0, 0.0, 1.0, 1.0)
+            .unwrap()
+            .push(&paths[0], None, None, None)
+            .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .trim_end()
+            .ends_with("</svg>"));
+    }
+
+    #[test]
+    fn instances_are_written_as_uses() {
+        let file_path = "symbols_test.svg";
+        let mut writer = LyonWriter::new();
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::origin());
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.line_to(Point2D::new(2.0, 0.0));
+        path_builder.end(true);
+        let symbol = writer
+            .define_symbol(&path_builder.build(), Some(fill(Color::black(), 1.0)), None)
+            .expect("Symbol should be writable!");
+        let instances: Vec<NodeId> = (0..3)
+            .map(|i| {
+                writer
+                    .push_instance(symbol, SvgTransform::from_translate(i as f32 * 3.0, 0.0))
+                    .expect("Symbol was defined!")
+            })
+            .collect();
+        assert!(matches!(
+            writer.push_instance(SymbolId(1), SvgTransform::identity()),
+            Err(LyonTranslationError::UnknownSymbol(SymbolId(1)))
+        ));
+        // the symbol draws its own fill and stroke
+        assert!(matches!(
+            writer.set_fill(instances[0], None),
+            Err(LyonTranslationError::WrongNodeKind(_))
+        ));
+        assert!(matches!(
+            writer.set_stroke(instances[1], None),
+            Err(LyonTranslationError::WrongNodeKind(_))
+        ));
+        let svg = written(writer, file_path);
+        assert_eq!(svg.matches("<symbol").count(), 1);
+        assert_eq!(svg.matches("<use").count(), 3);
+        assert_eq!(svg.matches(" d=").count(), 1);
+    }
+
+    #[test]
+    fn repeated_geometry_is_shared() {
+        let file_path = "dedup_test.svg";
+        let mut writer = LyonWriter::new();
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::origin());
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.line_to(Point2D::new(2.0, 0.0));
+        path_builder.end(true);
+        let path = path_builder.build();
+        for i in 0..3 {
+            writer
+                .push(
+                    &path,
+                    Some(fill(Color::new_rgb(i * 50, 0, 0), 1.0)),
+                    None,
+                    Some(SvgTransform::from_translate(i as f32 * 3.0, 0.0)),
+                )
+                .expect("Path should be writable!");
+        }
+        let data: Vec<_> = writer
+            .nodes()
+            .map(|(_, node)| match node {
+                Node::Path(p) => p.data.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert!(Arc::ptr_eq(&data[0], &data[1]) && Arc::ptr_eq(&data[1], &data[2]));
+        let svg = written(writer, file_path);
+        assert_eq!(svg.matches(" d=").count(), 1);
+        assert_eq!(svg.matches("<use").count(), 3);
+        assert!(svg.contains("fill=\"#640000\""));
+    }
+
+    #[test]
+    fn removed_geometry_is_released() {
+        let mut writer = LyonWriter::new();
+        let path = segment(0.0);
+        let a = writer.push(&path, None, None, None).unwrap();
+        let b = writer.push(&path, None, None, None).unwrap();
+        let group = writer.group(|g| {
+            g.push(&path, None, None, None).unwrap();
+            g.push(&path, None, None, None).unwrap();
+            g.node_id()
+        });
+        assert_eq!(writer.geometries.len(), 1);
+        writer.remove(a).unwrap();
+        writer.remove(group).unwrap();
+        assert_eq!(writer.geometries.len(), 1);
+        // the removed node keeps its data, but the writer does not share it anymore
+        let removed = writer.remove(b).unwrap();
+        assert!(writer.geometries.is_empty());
+        assert!(matches!(removed, Node::Path(p) if Arc::strong_count(&p.data) == 1));
+    }
+
+    #[test]
+    fn bbox_is_tracked_on_push() {
+        let mut writer = LyonWriter::new();
+        assert!(writer.bbox().is_none());
+        // a quadratic whose control point lies well outside of the curve
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::origin());
+        path_builder.quadratic_bezier_to(Point2D::new(1.0, 2.0), Point2D::new(2.0, 0.0));
+        path_builder.end(false);
+        writer
+            .push(&path_builder.build(), None, None, None)
+            .expect("Path should be writable!");
+        let bbox = writer.bbox().unwrap();
+        assert_eq!((bbox.left(), bbox.right()), (0.0, 2.0));
+        assert!((bbox.bottom() - 1.0).abs() < 1e-5);
+        writer
+            .push_png(&[], SvgTransform::from_translate(-1.0, -1.0), 1.0, 1.0)
+            .expect("Image should be writable!");
+        let bbox = writer.bbox().unwrap();
+        assert_eq!((bbox.left(), bbox.top()), (-1.0, -1.0));
+        let writer = writer.with_transform(SvgTransform::from_scale(2.0, 2.0));
+        assert_eq!(writer.bbox().unwrap().right(), 4.0);
+    }
+
+    #[test]
+    fn push_many_keeps_order() {
+        let mut writer = LyonWriter::new();
+        let paths: Vec<_> = (0..64)
+            .map(|i| {
+                let mut path_builder = Path::builder();
+                path_builder.begin(Point2D::new(i as f32, 0.0));
+                path_builder.line_to(Point2D::new(i as f32, 1.0));
+                path_builder.end(false);
+                let stroke = stroke(Color::new_rgb(i as u8, 0, 0), 1.0, 1.0);
+                (path_builder.build(), None, Some(stroke), None)
+            })
+            .collect();
+        let ids = writer.push_many(&paths).expect("Paths should be writable!");
+        assert_eq!(writer.nodes().count(), 64);
+        for (i, (id, node)) in writer.nodes().enumerate() {
+            assert_eq!(id, ids[i]);
+            let Node::Path(path) = node else {
+                unreachable!()
+            };
+            assert_eq!(path.data.points()[0].x, i as f32);
+            assert!(matches!(
+                path.stroke.as_ref().unwrap().paint,
+                Paint::Color(c) if c.red == i as u8
+            ));
+        }
+        assert_eq!(writer.bbox().unwrap().right(), 63.0);
+    }
+
+    #[test]
+    fn own_nodes_are_written() {
+        let file_path = "nodes_test.svg";
+        let mut writer = LyonWriter::new();
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::origin());
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.line_to(Point2D::new(2.0, 0.0));
+        path_builder.end(true);
+        let gradient = Paint::LinearGradient(Arc::new(LinearGradient {
+            id: "fade".to_string(),
+            x1: 0.0,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 0.0,
+            units: Units::ObjectBoundingBox,
+            transform: SvgTransform::identity(),
+            spread_method: SpreadMethod::Pad,
+            stops: vec![
+                Stop {
+                    offset: 0.0,
+                    color: Color::black(),
+                    opacity: 1.0,
+                },
+                Stop {
+                    offset: 1.0,
+                    color: Color::white(),
+                    opacity: 0.5,
+                },
+            ],
+        }));
+        let path = PathNode::new(
+            &path_builder.build(),
+            Some(Fill {
+                paint: gradient,
+                ..Default::default()
+            }),
+            None,
+            SvgTransform::from_translate(1.0, 0.0),
+        )
+        .expect("Path should be translatable!");
+        writer
+            .push_group(vec![Node::Path(path)], SvgTransform::from_scale(2.0, 2.0))
+            .expect("Group should be writable!");
+        assert_eq!(writer.bbox().unwrap().right(), 6.0);
+        let svg = written(writer, file_path);
+        assert_eq!(svg.matches("<linearGradient id=\"fade\"").count(), 1);
+        assert!(svg.contains("stop-opacity=\"0.5\""));
+        assert!(svg.contains("fill=\"url(#fade)\""));
+        assert!(svg.contains("transform=\"matrix(2 0 0 2 0 0)\""));
+    }
+
+    #[test]
+    fn patterns_are_written_with_their_paints() {
+        let file_path = "pattern_test.svg";
+        let mut writer = LyonWriter::new();
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::origin());
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.line_to(Point2D::new(2.0, 0.0));
+        path_builder.end(true);
+        let path = path_builder.build();
+        let gradient = Paint::RadialGradient(Arc::new(RadialGradient {
+            id: "glow".to_string(),
+            cx: 0.5,
+            cy: 0.5,
+            r: 0.5,
+            fx: 0.5,
+            fy: 0.5,
+            units: Units::ObjectBoundingBox,
+            transform: SvgTransform::identity(),
+            spread_method: SpreadMethod::Pad,
+            stops: vec![],
+        }));
+        let dot = PathNode::new(
+            &path,
+            Some(Fill {
+                paint: gradient,
+                ..Default::default()
+            }),
+            None,
+            SvgTransform::identity(),
+        )
+        .expect("Path should be translatable!");
+        let pattern = Paint::Pattern(Arc::new(Pattern {
+            id: "dots".to_string(),
+            units: Units::UserSpaceOnUse,
+            content_units: Units::UserSpaceOnUse,
+            transform: SvgTransform::identity(),
+            rect: Rect::from_xywh(0.0, 0.0, 4.0, 4.0).unwrap(),
+            children: vec![Node::Path(dot)],
+        }));
+        let fill = Fill {
+            paint: pattern,
+            ..Default::default()
+        };
+        writer
+            .push(&path, Some(fill.clone()), None, None)
+            .expect("Path should be translatable!");
+        writer
+            .push(
+                &path,
+                None,
+                Some(Stroke {
+                    paint: fill.paint,
+                    ..Default::default()
+                }),
+                None,
+            )
+            .expect("Path should be translatable!");
+        let svg = written(writer, file_path);
+        assert_eq!(svg.matches("<pattern id=\"dots\"").count(), 1);
+        assert!(svg.contains("patternUnits=\"userSpaceOnUse\""));
+        assert!(!svg.contains("patternContentUnits"));
+        let gradient = svg.find("<radialGradient id=\"glow\"").unwrap();
+        assert!(gradient < svg.find("<pattern").unwrap());
+        assert!(svg.contains("fill=\"url(#dots)\""));
+        assert!(svg.contains("stroke=\"url(#dots)\""));
+    }
+
+    #[test]
+    fn groups_nest() {
+        let file_path = "groups_test.svg";
+        let mut writer = LyonWriter::new();
+        let path = segment(0.0);
+        writer
+            .group(|outer| {
+                outer
+                    .id("outer")
+                    .transform(SvgTransform::from_translate(10.0, 0.0));
+                outer.push(&path, None, Some(stroke(Color::black(), 1.0, 1.0)), None)?;
+                outer.group(|inner| {
+                    inner
+                        .opacity(0.25)
+                        .blend_mode(BlendMode::Screen)
+                        .transform(SvgTransform::from_scale(3.0, 3.0));
+                    inner.push(&path, None, Some(stroke(Color::black(), 1.0, 1.0)), None)
+                })
+            })
+            .expect("Paths should be writable!");
+        assert_eq!(writer.nodes().count(), 4);
+        let bbox = writer.bbox().unwrap();
+        assert_eq!((bbox.left(), bbox.right()), (10.0, 13.0));
+        let svg = written(writer, file_path);
+        assert!(svg.contains("<g id=\"outer\" transform=\"matrix(1 0 0 1 10 0)\">"));
+        assert!(svg.contains(
+            "<g opacity=\"0.25\" transform=\"matrix(3 0 0 3 0 0)\" \
+             style=\"mix-blend-mode:screen;isolation:auto\">"
+        ));
+    }
+
+    #[test]
+    fn nodes_can_be_edited() {
+        let mut writer = LyonWriter::new();
+        let a = writer.push(&segment(0.0), None, None, None).unwrap();
+        let b = writer.push(&segment(5.0), None, None, None).unwrap();
+        let child = writer
+            .group(|g| {
+                g.transform(SvgTransform::from_translate(0.0, 10.0));
+                g.push(&segment(0.0), None, None, None)
+            })
+            .unwrap();
+        let group = writer.parent(child).unwrap();
+        assert_eq!(writer.children(group), &[child]);
+        assert_eq!(writer.node_bbox(child).unwrap().bottom(), 11.0);
+
+        writer.set_fill(a, Some(fill(Color::white(), 1.0))).unwrap();
+        assert!(matches!(writer.get(a), Some(Node::Path(p)) if p.fill.is_some()));
+        assert!(matches!(
+            writer.set_fill(group, None),
+            Err(LyonTranslationError::WrongNodeKind(_))
+        ));
+        writer.set_geometry(a, &segment(-3.0)).unwrap();
+        assert_eq!(writer.bbox().unwrap().left(), -3.0);
+        writer.remove(a).unwrap();
+        assert_eq!(writer.bbox().unwrap().left(), 0.0);
+        LyonWriter::new().group(|g| {
+            let open = g.node_id();
+            assert!(matches!(
+                g.remove(open),
+                Err(LyonTranslationError::OpenGroup(_))
+            ));
+        });
+        assert!(matches!(
+            writer.set_transform(a, SvgTransform::identity()),
+            Err(LyonTranslationError::UnknownNode(_))
+        ));
+
+        writer.raise_to_top(b).unwrap();
+        let order: Vec<NodeId> = writer.nodes().map(|(id, _)| id).collect();
+        assert_eq!(order, [group, child, b]);
+        writer.lower(b).unwrap();
+        assert_eq!(writer.nodes().next().unwrap().0, b);
+    }
+
+    #[test]
+    fn z_index_and_policy_sort_on_write() {
+        let file_path = "sort_test.svg";
+        let mut writer = LyonWriter::new().with_sort_policy(SortPolicy {
+            images_at_bottom: true,
+            ..Default::default()
+        });
+        let path = segment(0.0);
+        let red = writer
+            .push(
+                &path,
+                Some(fill(Color::new_rgb(255, 0, 0), 1.0)),
+                None,
+                None,
+            )
+            .unwrap();
+        writer
+            .push(
+                &path,
+                Some(fill(Color::new_rgb(0, 255, 0), 1.0)),
+                None,
+                None,
+            )
+            .unwrap();
+        let image = writer
+            .push_png(&[], SvgTransform::identity(), 1.0, 1.0)
+            .unwrap();
+        writer.set_z_index(red, 1).unwrap();
+        writer.set_z_index(image, 5).unwrap();
+        assert_eq!(writer.z_index(red), Some(1));
+        let svg = written(writer, file_path);
+        let image = svg.find("<image").unwrap();
+        let green = svg.find("#00ff00").unwrap();
+        let red = svg.find("#ff0000").unwrap();
+        assert!(image < green && green < red);
+    }
+
+    #[test]
+    fn appended_nodes_keep_their_z_index() {
+        let path = segment(0.0);
+        let mut part = LyonWriter::new();
+        let red = part
+            .push(
+                &path,
+                Some(fill(Color::new_rgb(255, 0, 0), 1.0)),
+                None,
+                None,
+            )
+            .unwrap();
+        part.push(
+            &path,
+            Some(fill(Color::new_rgb(0, 255, 0), 1.0)),
+            None,
+            None,
+        )
+        .unwrap();
+        part.set_z_index(red, 1).unwrap();
+        let mut writer = LyonWriter::new();
+        writer.append(part);
+        let svg = writer.to_svg_string().unwrap();
+        assert!(svg.find("#00ff00").unwrap() < svg.find("#ff0000").unwrap());
+    }
+
+    #[test]
+    fn layers_are_reused_and_filtered() {
+        let file_path = "layers_test.svg";
+        let mut writer = LyonWriter::new();
+        let base = writer
+            .layer("Base")
+            .push(&segment(0.0), None, None, None)
+            .unwrap();
+        writer
+            .layer("Notes")
+            .visible(false)
+            .push(&segment(10.0), None, None, None)
+            .unwrap();
+        writer
+            .layer("Base")
+            .push(&segment(2.0), None, None, None)
+            .unwrap();
+        let layer = writer.parent(base).unwrap();
+        assert_eq!(writer.children(layer).len(), 2);
+        assert_eq!(writer.bbox().unwrap().right(), 11.0);
+        let mut moved = LyonWriter::new();
+        moved
+            .layer("Base")
+            .push(&segment(0.0), None, None, None)
+            .unwrap();
+        moved
+            .layer("Notes")
+            .push(&segment(10.0), None, None, None)
+            .unwrap();
+        // moving a reopened layer drops its previous extent
+        moved
+            .layer("Notes")
+            .transform(SvgTransform::from_translate(-10.0, 0.0));
+        assert_eq!(moved.bbox().unwrap().right(), 1.0);
+
+        let mut filtered = LyonWriter::new();
+        filtered
+            .layer("Base")
+            .push(&segment(0.0), None, None, None)
+            .unwrap();
+        filtered
+            .layer("Notes")
+            .push(&segment(10.0), None, None, None)
+            .unwrap();
+        let filtered = written(filtered.with_exported_layers(["Base"]), file_path);
+        assert!(filtered.contains("inkscape:label=\"Base\""));
+        assert!(!filtered.contains("Notes"));
+        assert!(filtered.contains("viewBox=\"0 0 1 1\""));
+
+        let svg = written(writer, file_path);
+        assert!(svg.contains("xmlns:inkscape="));
+        assert!(svg.contains(
+            "<g id=\"layer2\" inkscape:groupmode=\"layer\" inkscape:label=\"Notes\" \
+             style=\"display:none\">"
+        ));
+    }
+
+    #[test]
+    fn appended_layers_are_merged() {
+        let layered = |labels: [&str; 2]| {
+            let mut writer = LyonWriter::new();
+            for (i, label) in labels.into_iter().enumerate() {
+                writer
+                    .layer(label)
+                    .push(&segment(i as f32), None, None, None)
+                    .unwrap();
+            }
+            writer
+        };
+        let mut writer = layered(["Base", "Notes"]);
+        writer.append(layered(["Grid", "Notes"]));
+        let notes = writer
+            .layer("Notes")
+            .push(&segment(5.0), None, None, None)
+            .unwrap();
+        assert_eq!(
+            writer.attributes(writer.parent(notes).unwrap()).unwrap().id,
+            "layer2"
+        );
+        let svg = writer
+            .with_exported_layers(["Grid", "Notes"])
+            .to_svg_string()
+            .unwrap();
+        assert!(!svg.contains("Base"));
+        assert!(
+            svg.contains("<g id=\"layer3\" inkscape:groupmode=\"layer\" inkscape:label=\"Grid\">")
+        );
+        assert!(
+            svg.contains("<g id=\"layer4\" inkscape:groupmode=\"layer\" inkscape:label=\"Notes\">")
+        );
+        assert!(!svg.contains("id=\"layer1\""));
+        assert_eq!(svg.matches("inkscape:label=\"Notes\"").count(), 2);
+    }
+
+    #[test]
+    fn attributes_are_written() {
+        let file_path = "attributes_test.svg";
+        let mut writer = LyonWriter::new();
+        let wire = writer.push(&segment(0.0), None, None, None).unwrap();
+        writer
+            .set_attributes(
+                wire,
+                Attributes::new()
+                    .with_id("wire-1")
+                    .with_class("wire")
+                    .with_class("selected")
+                    .with_data("net", "a&b"),
+            )
+            .unwrap();
+        writer
+            .group(|g| {
+                g.id("board").class("pcb").data("layer", "top");
+                g.push(&segment(2.0), None, None, None)
+            })
+            .unwrap();
+        assert_eq!(writer.attributes(wire).unwrap().id, "wire-1");
+
+        let svg = written(writer, file_path);
+        assert!(svg.contains("<path id=\"wire-1\" class=\"wire selected\" data-net=\"a&amp;b\""));
+        assert!(svg.contains("<g id=\"board\" class=\"pcb\" data-layer=\"top\">"));
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let path = segment(0.0);
+        let mut writer = LyonWriter::new();
+        let wire = writer.push(&path, None, None, None).unwrap();
+        for attributes in [
+            Attributes::new().with_class("a b"),
+            Attributes::new().with_class("1st"),
+            Attributes::new().with_data("x\"><script", "1"),
+            Attributes::new().with_data("", "1"),
+            Attributes::new().with_id("wire 1"),
+        ] {
+            assert!(matches!(
+                writer.set_attributes(wire, attributes),
+                Err(LyonTranslationError::InvalidName(_))
+            ));
+        }
+        assert!(matches!(
+            writer.push_styled(&path, "a=b", None),
+            Err(LyonTranslationError::InvalidName(_))
+        ));
+        writer
+            .set_attributes(wire, Attributes::new().with_class("été_2-b"))
+            .unwrap();
+        // names set without checking them fail on write
+        writer
+            .group(|g| {
+                g.data("a b", "1");
+                g.push(&path, None, None, None)
+            })
+            .unwrap();
+        assert!(matches!(
+            writer.to_svg_string(),
+            Err(LyonTranslationError::InvalidName(name)) if name == "a b"
+        ));
+        let mut writer = LyonWriter::new();
+        writer.push(&path, None, None, None).unwrap();
+        writer.define_style("<b>", Style::default());
+        assert!(matches!(
+            writer.to_svg_string(),
+            Err(LyonTranslationError::InvalidName(_))
+        ));
+
+        // the ids generated by the writer stay unique
+        let mut writer = LyonWriter::new();
+        let wire = writer.layer("Base").push(&path, None, None, None).unwrap();
+        for id in ["layer1", "symbol0", "geometry12"] {
+            assert!(matches!(
+                writer.set_attributes(wire, Attributes::new().with_id(id)),
+                Err(LyonTranslationError::ReservedId(_))
+            ));
+        }
+        writer
+            .set_attributes(wire, Attributes::new().with_id("layer-1"))
+            .unwrap();
+        writer
+            .group(|g| {
+                g.id("geometry0");
+                g.push(&path, None, None, None)
+            })
+            .unwrap();
+        assert!(matches!(
+            writer.to_svg_string(),
+            Err(LyonTranslationError::ReservedId(id)) if id == "geometry0"
+        ));
+    }
+
+    #[test]
+    fn titles_and_links_are_written() {
+        let file_path = "links_test.svg";
+        let mut writer = LyonWriter::new();
+        let cell = writer.push(&segment(0.0), None, None, None).unwrap();
+        writer
+            .set_attributes(
+                cell,
+                Attributes::new()
+                    .with_title("Cell <3>")
+                    .with_desc("A cell")
+                    .with_href("cells/3.html"),
+            )
+            .unwrap();
+        writer
+            .group(|g| {
+                g.title("Tissue");
+                g.push(&segment(2.0), None, None, None)
+            })
+            .unwrap();
+
+        let svg = written(writer, file_path);
+        assert!(svg.contains("xmlns:xlink="));
+        assert!(svg.contains("<a xlink:href=\"cells/3.html\">"));
+        assert!(svg.contains("<title>Cell &lt;3&gt;</title>"));
+        let svg: String = svg.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(svg.contains("<desc>A cell</desc> </path> </a>"));
+        assert!(svg.contains("<g> <title>Tissue</title>"));
+    }
+
+    #[test]
+    fn metadata_is_written() {
+        let file_path = "metadata_test.svg";
+        let mut writer = LyonWriter::new().with_metadata(Metadata {
+            title: Some("Counts".to_string()),
+            lang: Some("en".to_string()),
+            creator: Some("Lab & co".to_string()),
+            license: Some("https://creativecommons.org/licenses/by/4.0/".to_string()),
+            ..Default::default()
+        });
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::new(0.0, 0.0));
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.end(false);
+        writer
+            .push(&path_builder.build(), None, None, None)
+            .unwrap();
+
+        let svg = written(writer, file_path);
+        assert!(
+            svg.contains(" lang=\"en\" xml:lang=\"en\" role=\"img\" aria-labelledby=\"title\">")
+        );
+        assert!(!svg.contains("aria-describedby"));
+        assert!(svg.contains("<title id=\"title\">Counts</title>"));
+        assert!(svg.contains("<dc:creator>Lab &amp; co</dc:creator>"));
+        assert!(svg.contains(
+            "<cc:license rdf:resource=\"https://creativecommons.org/licenses/by/4.0/\"/>"
+        ));
+        assert!(svg.find("</metadata>") < svg.find("<path"));
+    }
+
+    #[test]
+    fn write_options_format_the_svg() {
+        let file_path = "options_test.svg";
+        let mut writer = LyonWriter::new().with_write_options(WriteOptions {
+            coordinates_precision: 2,
+            indent: Indent::None,
+            quotes: AttributeQuotes::Single,
+            relative_paths: true,
+            xml_declaration: true,
+            ..Default::default()
+        });
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::new(1.0, 1.0));
+        path_builder.line_to(Point2D::new(2.333333, 1.0));
+        path_builder.line_to(Point2D::new(2.0, 3.0));
+        path_builder.end(true);
+        path_builder.begin(Point2D::new(5.0, 5.0));
+        path_builder.line_to(Point2D::new(6.0, 5.0));
+        path_builder.end(false);
+        writer
+            .push(&path_builder.build(), None, None, None)
+            .unwrap();
+
+        let svg = written(writer, file_path);
+        assert!(svg.starts_with("<?xml version='1.0' encoding='UTF-8'?><svg width='5' "));
+        assert!(!svg.contains('\n'));
+        assert!(svg.contains(" d='m 1 1 l 1.33 0 l -0.33 2 l -1 -2 z m 4 4 l 1 0'"));
+    }
+
+    #[test]
+    fn large_and_non_finite_numbers() {
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::origin());
+        path_builder.line_to(Point2D::new(1e20, 1.0));
+        path_builder.end(false);
+        let path = path_builder.build();
+        let mut writer = LyonWriter::new();
+        writer.push(&path, None, None, None).unwrap();
+        let svg = writer.to_svg_string().unwrap();
+        // the `f32` closest to 1e20
+        assert!(svg.contains("100000002004087730000"));
+        assert!(!svg.contains("9223372036854775807"));
+
+        let mut writer = LyonWriter::new();
+        let id = writer.push(&path, None, None, None).unwrap();
+        let stretch = SvgTransform::from_scale(f32::INFINITY, 1.0);
+        writer.set_transform(id, stretch).unwrap();
+        assert!(matches!(
+            writer.to_svg_string(),
+            Err(LyonTranslationError::NotFinite(_))
+        ));
+        let mut streamed = LyonStreamWriter::new(std::io::sink(), 0.0, 0.0, 1.0, 1.0).unwrap();
+        let mut dashed = stroke(Color::black(), 1.0, 1.0);
+        dashed.dasharray = Some(vec![1.0, f32::NAN]);
+        assert!(matches!(
+            streamed.push(&path, None, Some(dashed), None),
+            Err(LyonTranslationError::NotFinite(_))
+        ));
+    }
+
+    #[test]
+    fn output_is_reproducible() {
+        let build = || {
+            let mut writer = LyonWriter::new().with_write_options(WriteOptions {
+                id_prefix: "fig1-".to_string(),
+                ..Default::default()
+            });
+            let mut path_builder = Path::builder();
+            path_builder.begin(Point2D::new(0.0, 0.0));
+            path_builder.line_to(Point2D::new(1.0 / 3.0, 1.0));
+            path_builder.end(false);
+            let path = path_builder.build();
+            let symbol = writer.define_symbol(&path, None, None).unwrap();
+            writer
+                .push_instance(symbol, SvgTransform::from_translate(1.0, 0.0))
+                .unwrap();
+            for _ in 0..2 {
+                writer.push(&path, None, None, None).unwrap();
+            }
+            writer.layer("Notes").push(&path, None, None, None).unwrap();
+            writer
+        };
+        let svg = build().to_svg_string().unwrap();
+        assert_eq!(svg, build().to_svg_string().unwrap());
+        assert!(svg.contains("<symbol id=\"fig1-symbol0\""));
+        assert!(svg.contains("xlink:href=\"#fig1-symbol0\""));
+        assert!(svg.contains("<path id=\"fig1-geometry0\" d=\"M 0 0 L 0.33333334 1\"/>"));
+        assert!(svg.contains("<g id=\"fig1-layer1\""));
+
+        let snapshot = "snapshots/reproducible.svg";
+        assert_snapshot(&svg, snapshot);
+        assert_snapshot(&build().to_svg_string().unwrap(), snapshot);
+        std::fs::remove_dir_all("snapshots").unwrap();
+    }
+
+    #[test]
+    fn svgz_and_data_uri_outputs() {
+        use base64::Engine;
+        use std::io::Read;
+
+        let build = || {
+            let mut writer = LyonWriter::new();
+            let mut path_builder = Path::builder();
+            path_builder.begin(Point2D::new(0.0, 0.0));
+            path_builder.line_to(Point2D::new(1.0, 1.0));
+            path_builder.end(false);
+            writer
+                .push(&path_builder.build(), None, None, None)
+                .unwrap();
+            writer
+        };
+        let svg = build().to_svg_string().unwrap();
+
+        let file_path = "compressed_test.SVGZ";
+        build().write(file_path).expect("Writing should not panic!");
+        let compressed = std::fs::read(file_path).unwrap();
+        std::fs::remove_file(file_path).unwrap();
+        assert_eq!(compressed, build().to_svgz().unwrap());
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, svg);
+
+        let uri = build().to_data_uri().unwrap();
+        let encoded = uri.strip_prefix("data:image/svg+xml;base64,").unwrap();
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        assert_eq!(decoded, svg.as_bytes());
+    }
+
+    #[test]
+    fn rgba_pixels_are_encoded_as_png() {
+        use base64::Engine;
+
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 128];
+        let placement = Rect::from_xywh(1.0, 2.0, 20.0, 10.0).unwrap();
+        let mut writer = LyonWriter::new();
+        assert!(matches!(
+            writer.push_rgba(2, 2, &pixels, placement),
+            Err(LyonTranslationError::WrongImageData { len: 8, .. })
+        ));
+        writer.push_rgba(2, 1, &pixels, placement).unwrap();
+        let svg = writer.to_svg_string().unwrap();
+        assert!(svg.contains(r#"x="1" y="2" width="20" height="10""#));
+
+        let encoded = svg
+            .split("data:image/png;base64,")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        let png = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(decoded, pixels);
+
+        #[cfg(feature = "image")]
+        {
+            let image = image::RgbaImage::from_raw(2, 1, pixels.to_vec()).unwrap();
+            let mut writer = LyonWriter::new();
+            writer.push_image(&image.into(), placement).unwrap();
+            assert!(writer.to_svg_string().unwrap().contains(encoded));
+        }
+    }
+
+    #[test]
+    fn encoded_images_keep_their_format() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        let webp = *b"RIFF\x04\x00\x00\x00WEBPVP8 ";
+        let svg = "<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(ImageFormat::detect(&jpeg), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::detect(b"GIF89a..."), Some(ImageFormat::Gif));
+        assert_eq!(ImageFormat::detect(&webp), Some(ImageFormat::Webp));
+        assert_eq!(ImageFormat::detect(svg.as_bytes()), Some(ImageFormat::Svg));
+        assert_eq!(ImageFormat::detect(b"not an image"), None);
+
+        let placement = Rect::from_xywh(0.0, 0.0, 4.0, 3.0).unwrap();
+        let mut writer = LyonWriter::new();
+        assert!(matches!(
+            writer.push_encoded_image(b"not an image", placement),
+            Err(LyonTranslationError::UnknownImageFormat)
+        ));
+        writer.push_encoded_image(&jpeg, placement).unwrap();
+        writer
+            .push_encoded_image_as(&webp, ImageFormat::Webp, placement)
+            .unwrap();
+        writer.push_svg(svg, placement);
+        let written = writer.to_svg_string().unwrap();
+        assert!(written.contains("data:image/jpeg;base64,/9j/4AAQ"));
+        assert!(written.contains("data:image/webp;base64,UklGR"));
+        assert!(written.contains("data:image/svg+xml;base64,PD94bWwg"));
+    }
+
+    #[test]
+    fn images_keep_their_transform_and_rendering() {
+        let mut writer = LyonWriter::new();
+        let transform = SvgTransform::from_row(0.0, 1.0, -1.0, 0.0, 4.0, 1.0);
+        let image = writer.push_png(&[], transform, 2.0, 1.0).unwrap();
+        // rotated by 90° around the origin, then moved
+        let bbox = writer.bbox().unwrap();
+        assert_eq!((bbox.left(), bbox.right()), (3.0, 4.0));
+        assert_eq!((bbox.top(), bbox.bottom()), (1.0, 3.0));
+        assert!(matches!(
+            writer.push_png(&[], transform, 0.0, 1.0),
+            Err(LyonTranslationError::WrongBoundingBox { max_x, max_y, .. })
+                if max_x == 0.0 && max_y == 1.0
+        ));
+
+        writer
+            .set_image_rendering(image, ImageRendering::Pixelated)
+            .unwrap();
+        let aspect = AspectRatio {
+            align: Align::XMinYMax,
+            slice: true,
+        };
+        writer.set_aspect_ratio(image, aspect).unwrap();
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::new(0.0, 0.0));
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.end(false);
+        let path = writer
+            .push(&path_builder.build(), None, None, None)
+            .unwrap();
+        assert!(matches!(
+            writer.set_image_rendering(path, ImageRendering::Pixelated),
+            Err(LyonTranslationError::WrongNodeKind(_))
+        ));
+
+        let svg = writer.to_svg_string().unwrap();
+        assert!(svg.contains(
+            r#"<image x="0" y="0" width="2" height="1" image-rendering="pixelated" preserveAspectRatio="xMinYMax slice" transform="matrix(0 1 -1 0 4 1)""#
+        ));
+    }
+
+    #[test]
+    fn files_are_written_atomically() {
+        let file_path = "atomic_test/figures/atomic.svg";
+        let build = |options: FileOptions| {
+            let mut writer = LyonWriter::new().with_file_options(options);
+            let mut path_builder = Path::builder();
+            path_builder.begin(Point2D::new(0.0, 0.0));
+            path_builder.line_to(Point2D::new(1.0, 1.0));
+            path_builder.end(false);
+            writer
+                .push(&path_builder.build(), None, None, None)
+                .unwrap();
+            writer
+        };
+        let options = FileOptions {
+            atomic: true,
+            create_dirs: true,
+            overwrite: false,
+        };
+        build(options).write(file_path).unwrap();
+        assert!(std::fs::read_to_string(file_path)
+            .unwrap()
+            .ends_with("</svg>"));
+        let err = build(options).write(file_path).unwrap_err();
+        assert!(matches!(
+            err,
+            LyonTranslationError::IoWrite(e)
+                if e.downcast_ref::<std::io::Error>().unwrap().kind()
+                    == std::io::ErrorKind::AlreadyExists
+        ));
+        let no_dirs = FileOptions {
+            create_dirs: false,
+            ..Default::default()
+        };
+        assert!(build(no_dirs).write("atomic_test/missing/a.svg").is_err());
+        build(FileOptions {
+            overwrite: true,
+            ..options
+        })
+        .write(file_path)
+        .unwrap();
+        // no temporary file is left behind
+        let files = std::fs::read_dir("atomic_test/figures").unwrap().count();
+        std::fs::remove_dir_all("atomic_test").unwrap();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn styles_are_written_as_classes() {
+        let file_path = "styles_test.svg";
+        let mut writer = LyonWriter::new();
+        let mut path_builder = Path::builder();
+        path_builder.begin(Point2D::new(0.0, 0.0));
+        path_builder.line_to(Point2D::new(1.0, 1.0));
+        path_builder.end(false);
+        let path = path_builder.build();
+        let wire = writer.push_styled(&path, "wire", None).unwrap();
+        writer
+            .set_attributes(wire, Attributes::new().with_class("selected"))
+            .unwrap();
+        writer.define_style(
+            "wire",
+            Style {
+                fill: None,
+                stroke: Some(stroke(Color::white(), 0.5, 2.0)),
+            },
+        );
+        writer.define_style(
+            "wire",
+            Style {
+                fill: None,
+                stroke: Some(stroke(Color::black(), 0.5, 2.0)),
+            },
+        );
+        assert_eq!(
+            writer.style("wire").unwrap().stroke.as_ref().unwrap().width,
+            2.0
+        );
+
+        let svg = written(writer, file_path);
+        assert!(svg.contains(
+            "<style>\n        .wire{fill:none;stroke:#000000;stroke-opacity:0.5;stroke-width:2}\n    </style>"
+        ));
+        assert!(svg.contains("<path class=\"wire selected\" d="));
+        assert_eq!(svg.matches("stroke").count(), 3);
+    }
+
+    #[test]
+    fn writer_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<LyonWriter<NoText>>();
+        assert_send_sync::<LyonWriter<Option<usvg::fontdb::Database>>>();
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let mut writer = LyonWriter::new();
+                    let mut path_builder = Path::builder();
+                    path_builder.begin(Point2D::new(i as f32, 0.0));
+                    path_builder.line_to(Point2D::new(i as f32, 1.0));
+                    path_builder.end(false);
+                    writer
+                        .push(&path_builder.build(), None, None, None)
+                        .expect("Path should be writable!");
+                    writer
+                })
+            })
+            .collect();
+        let mut writer = LyonWriter::new();
+        for handle in handles {
+            writer.append(handle.join().unwrap());
+        }
+        assert_eq!(writer.nodes().count(), 4);
+        assert_eq!(writer.bbox().unwrap().right(), 3.0);
+    }
 }
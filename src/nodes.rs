@@ -0,0 +1,579 @@
+//! Storage of the pushed nodes, addressed by [`NodeId`] so that they can be edited in place.
+use std::sync::Arc;
+
+use lyon_path::Path;
+use usvg::tiny_skia_path::Path as PathData;
+
+use crate::scene::{check_name, check_reserved};
+use crate::{
+    geometry_hash, join_bbox, lyon_path_to_usvg, node_bbox, AspectRatio, Attributes, Fill,
+    GroupNode, ImageRendering, LyonTranslationError, LyonWriter, Node, Rect, Stroke, SvgTransform,
+};
+
+/// Handle to a node pushed to a [`LyonWriter`].
+///
+/// It stays valid until the node, or one of its ancestors, is [removed](LyonWriter::remove).
+///
+/// It is only an index into the writer that returned it, which is not checked: the id of a
+/// node of another writer, including the ids of a writer passed to
+/// [`append`](LyonWriter::append), refers to an unrelated node or to none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Rules to reorder the nodes on write, on top of their [z-index](LyonWriter::set_z_index).
+///
+/// They apply among siblings: a text inside of a group goes on top of the other children
+/// of the group, not of the whole SVG.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SortPolicy {
+    /// Draw text after everything else, whatever its z-index.
+    pub text_on_top: bool,
+    /// Draw images before everything else, whatever their z-index.
+    pub images_at_bottom: bool,
+}
+
+impl SortPolicy {
+    /// Order of the nodes of different kinds, before their z-index.
+    fn layer(&self, node: &Node) -> i8 {
+        match node {
+            Node::Image(_) if self.images_at_bottom => -1,
+            Node::Text(_) if self.text_on_top => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// A node with its place in the tree. Groups keep their children here, not in the [`Node`].
+struct Entry {
+    node: Node,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    z_index: i32,
+}
+
+/// The nodes of a [`LyonWriter`], flattened so that every node has a [`NodeId`].
+#[derive(Default)]
+pub(crate) struct Arena {
+    /// Indexed by [`NodeId`], `None` once removed.
+    entries: Vec<Option<Entry>>,
+    /// Top level nodes, in draw order.
+    roots: Vec<NodeId>,
+}
+
+impl Arena {
+    /// Insert `node` and its descendants as the last child of `parent`.
+    pub(crate) fn insert(&mut self, mut node: Node, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.entries.len());
+        let children = match node {
+            Node::Group(ref mut group) => std::mem::take(&mut group.children),
+            _ => Vec::new(),
+        };
+        self.entries.push(Some(Entry {
+            node,
+            parent,
+            children: Vec::new(),
+            z_index: 0,
+        }));
+        self.siblings_mut(parent).push(id);
+        for child in children {
+            self.insert(child, Some(id));
+        }
+        id
+    }
+
+    fn entry(&self, id: NodeId) -> Option<&Entry> {
+        self.entries.get(id.0)?.as_ref()
+    }
+
+    fn entry_mut(&mut self, id: NodeId) -> Option<&mut Entry> {
+        self.entries.get_mut(id.0)?.as_mut()
+    }
+
+    pub(crate) fn get(&self, id: NodeId) -> Option<&Node> {
+        self.entry(id).map(|entry| &entry.node)
+    }
+
+    pub(crate) fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.entry_mut(id).map(|entry| &mut entry.node)
+    }
+
+    fn siblings_mut(&mut self, parent: Option<NodeId>) -> &mut Vec<NodeId> {
+        match parent {
+            Some(parent) => {
+                &mut self
+                    .entry_mut(parent)
+                    .expect("parents outlive their children")
+                    .children
+            }
+            None => &mut self.roots,
+        }
+    }
+
+    /// Detach `id` from the tree and return it with its descendants.
+    pub(crate) fn remove(&mut self, id: NodeId) -> Option<Node> {
+        let parent = self.entry(id)?.parent;
+        self.siblings_mut(parent).retain(|sibling| *sibling != id);
+        Some(self.take_tree(id))
+    }
+
+    /// Take the entries of `id` and its descendants out, rebuilding its [`Node`].
+    fn take_tree(&mut self, id: NodeId) -> Node {
+        let entry = self.entries[id.0].take().expect("the tree holds valid ids");
+        let mut node = entry.node;
+        if let Node::Group(ref mut group) = node {
+            group.children = entry
+                .children
+                .into_iter()
+                .map(|child| self.take_tree(child))
+                .collect();
+        }
+        node
+    }
+
+    /// Move the trees of `other` under `parent`, keeping their z-index.
+    ///
+    /// Returns the new id of a node of `other` from its old id, if it was not removed.
+    pub(crate) fn append(
+        &mut self,
+        mut other: Arena,
+        parent: Option<NodeId>,
+    ) -> impl Fn(NodeId) -> Option<NodeId> {
+        let mut ids = vec![None; other.entries.len()];
+        for root in std::mem::take(&mut other.roots) {
+            self.move_tree(&mut other, root, parent, &mut ids);
+        }
+        move |id| ids.get(id.0).copied().flatten()
+    }
+
+    fn move_tree(
+        &mut self,
+        other: &mut Arena,
+        id: NodeId,
+        parent: Option<NodeId>,
+        ids: &mut [Option<NodeId>],
+    ) {
+        let entry = other.entries[id.0]
+            .take()
+            .expect("the tree holds valid ids");
+        let new_id = NodeId(self.entries.len());
+        self.entries.push(Some(Entry {
+            node: entry.node,
+            parent,
+            children: Vec::new(),
+            z_index: entry.z_index,
+        }));
+        self.siblings_mut(parent).push(new_id);
+        ids[id.0] = Some(new_id);
+        for child in entry.children {
+            self.move_tree(other, child, Some(new_id), ids);
+        }
+    }
+
+    /// All the nodes that were not removed, without their children.
+    pub(crate) fn nodes_mut(&mut self) -> impl Iterator<Item = &mut Node> {
+        self.entries
+            .iter_mut()
+            .flatten()
+            .map(|entry| &mut entry.node)
+    }
+
+    /// Rebuild the top level [`Node`]s, consuming the arena.
+    pub(crate) fn into_roots(mut self) -> Vec<Node> {
+        std::mem::take(&mut self.roots)
+            .into_iter()
+            .map(|root| self.take_tree(root))
+            .collect()
+    }
+
+    /// Transform from the coordinates of the parent of `id` to the root.
+    fn parent_transform(&self, id: NodeId) -> SvgTransform {
+        let mut transform = SvgTransform::identity();
+        let mut current = self.entry(id).and_then(|entry| entry.parent);
+        while let Some(parent) = current {
+            let entry = self.entry(parent).expect("parents outlive their children");
+            transform = transform.post_concat(node_transform(&entry.node));
+            current = entry.parent;
+        }
+        transform
+    }
+
+    /// Exact bounds of `id` and its descendants after applying `transform` on top of them.
+    pub(crate) fn subtree_bbox(&self, id: NodeId, transform: SvgTransform) -> Option<Rect> {
+        let entry = self.entry(id)?;
+        match entry.node {
+            Node::Group(ref group) => {
+                let transform = group.transform.post_concat(transform);
+                entry
+                    .children
+                    .iter()
+                    .filter_map(|child| self.subtree_bbox(*child, transform))
+                    .fold(None, join_bbox)
+            }
+            ref node => node_bbox(node, transform),
+        }
+    }
+
+    /// Bounds of the whole tree after applying `transform`.
+    pub(crate) fn bbox(&self, transform: SvgTransform) -> Option<Rect> {
+        self.roots
+            .iter()
+            .filter_map(|root| self.subtree_bbox(*root, transform))
+            .fold(None, join_bbox)
+    }
+
+    /// Stable sort of every list of siblings by `policy` and then by z-index.
+    pub(crate) fn sort(&mut self, policy: SortPolicy) {
+        let key = |arena: &Self, id: &NodeId| {
+            let entry = arena.entry(*id).expect("the tree holds valid ids");
+            (policy.layer(&entry.node), entry.z_index)
+        };
+        let mut roots = std::mem::take(&mut self.roots);
+        roots.sort_by_key(|id| key(self, id));
+        self.roots = roots;
+        for i in 0..self.entries.len() {
+            let Some(mut children) = self.entries[i]
+                .as_mut()
+                .map(|entry| std::mem::take(&mut entry.children))
+            else {
+                continue;
+            };
+            children.sort_by_key(|id| key(self, id));
+            self.entries[i].as_mut().expect("checked above").children = children;
+        }
+    }
+
+    /// Ids of all the nodes, parents before their children, in draw order.
+    fn ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            let entry = self.entry(id).expect("the tree holds valid ids");
+            stack.extend(entry.children.iter().rev());
+            Some(id)
+        })
+    }
+}
+
+fn node_transform(node: &Node) -> SvgTransform {
+    match node {
+        Node::Path(path) => path.transform,
+        Node::Image(image) => image.transform,
+        Node::Text(text) => text.transform,
+        Node::Group(group) => group.transform,
+    }
+}
+
+/// Visit the path data held by `node` and its descendants.
+fn for_each_data(node: &Node, f: &mut impl FnMut(&Arc<PathData>)) {
+    match node {
+        Node::Path(path) => f(&path.data),
+        Node::Group(group) => group.children.iter().for_each(|c| for_each_data(c, f)),
+        Node::Image(_) | Node::Text(_) => {}
+    }
+}
+
+impl<T> LyonWriter<T> {
+    /// The node behind `id`, if it was not removed.
+    ///
+    /// The `children` of a [`GroupNode`] are kept apart and listed by
+    /// [`children`](Self::children) instead.
+    pub fn get(&self, id: NodeId) -> Option<&Node> {
+        self.arena.get(id)
+    }
+
+    /// The group containing `id`, if any.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.arena.entry(id)?.parent
+    }
+
+    /// The children of the group `id`, in draw order.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.arena.entry(id).map_or(&[], |entry| &entry.children)
+    }
+
+    /// Iterate over all the nodes, parents before their children, in draw order.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> + '_ {
+        self.arena.ids().map(|id| {
+            let node = self.arena.get(id).expect("the tree holds valid ids");
+            (id, node)
+        })
+    }
+
+    /// Exact bounding box of a node in the coordinates of the SVG, like [`bbox`](Self::bbox).
+    pub fn node_bbox(&self, id: NodeId) -> Option<Rect> {
+        let transform = self
+            .arena
+            .parent_transform(id)
+            .post_concat(self.global_transform.unwrap_or_default());
+        self.arena.subtree_bbox(id, transform)
+    }
+
+    /// Replace the fill of a path or a text.
+    ///
+    /// Instances of a symbol are drawn with the fill of the symbol, so they are a
+    /// [`WrongNodeKind`](LyonTranslationError::WrongNodeKind).
+    pub fn set_fill(&mut self, id: NodeId, fill: Option<Fill>) -> Result<(), LyonTranslationError> {
+        if self.is_instance(id) {
+            return Err(LyonTranslationError::WrongNodeKind(id));
+        }
+        match self.node_mut(id)? {
+            Node::Path(path) => path.fill = fill,
+            Node::Text(text) => text.fill = fill,
+            Node::Image(_) | Node::Group(_) => return Err(LyonTranslationError::WrongNodeKind(id)),
+        }
+        Ok(())
+    }
+
+    /// Draw a path with the [`Style`](crate::Style) of `class` instead of its own fill and
+    /// stroke, or with them again if `None`.
+    pub fn set_style(
+        &mut self,
+        id: NodeId,
+        class: Option<String>,
+    ) -> Result<(), LyonTranslationError> {
+        if let Some(ref class) = class {
+            check_name(class)?;
+        }
+        match self.node_mut(id)? {
+            Node::Path(path) => path.style = class,
+            _ => return Err(LyonTranslationError::WrongNodeKind(id)),
+        }
+        Ok(())
+    }
+
+    /// Replace the [`Attributes`] of a node: its id, classes, `data-*` attributes, tooltip,
+    /// description and link.
+    ///
+    /// A text keeps them on the `<g>` holding its glyphs.
+    pub fn set_attributes(
+        &mut self,
+        id: NodeId,
+        attributes: Attributes,
+    ) -> Result<(), LyonTranslationError> {
+        attributes.check()?;
+        check_reserved(&attributes.id)?;
+        *self.node_mut(id)?.attributes_mut() = attributes;
+        Ok(())
+    }
+
+    /// The [`Attributes`] of a node, if it was not removed.
+    pub fn attributes(&self, id: NodeId) -> Option<&Attributes> {
+        Some(self.arena.get(id)?.attributes())
+    }
+
+    /// Replace the stroke of a path or a text, not of an instance of a symbol like
+    /// [`set_fill`](Self::set_fill).
+    pub fn set_stroke(
+        &mut self,
+        id: NodeId,
+        stroke: Option<Stroke>,
+    ) -> Result<(), LyonTranslationError> {
+        if self.is_instance(id) {
+            return Err(LyonTranslationError::WrongNodeKind(id));
+        }
+        match self.node_mut(id)? {
+            Node::Path(path) => path.stroke = stroke,
+            Node::Text(text) => text.stroke = stroke,
+            Node::Image(_) | Node::Group(_) => return Err(LyonTranslationError::WrongNodeKind(id)),
+        }
+        Ok(())
+    }
+
+    /// Replace the transform of any node.
+    pub fn set_transform(
+        &mut self,
+        id: NodeId,
+        transform: SvgTransform,
+    ) -> Result<(), LyonTranslationError> {
+        match self.node_mut(id)? {
+            Node::Path(path) => path.transform = transform,
+            Node::Image(image) => image.transform = transform,
+            Node::Text(text) => text.transform = transform,
+            Node::Group(group) => group.transform = transform,
+        }
+        self.invalidate_bbox();
+        Ok(())
+    }
+
+    /// Set how an image is scaled, for example [`ImageRendering::Pixelated`] to keep the
+    /// pixels of a heatmap sharp.
+    pub fn set_image_rendering(
+        &mut self,
+        id: NodeId,
+        rendering: ImageRendering,
+    ) -> Result<(), LyonTranslationError> {
+        match self.node_mut(id)? {
+            Node::Image(image) => image.rendering_mode = rendering,
+            _ => return Err(LyonTranslationError::WrongNodeKind(id)),
+        }
+        Ok(())
+    }
+
+    /// Set how an image is fitted in its rect when their aspect ratios differ.
+    pub fn set_aspect_ratio(
+        &mut self,
+        id: NodeId,
+        aspect: AspectRatio,
+    ) -> Result<(), LyonTranslationError> {
+        match self.node_mut(id)? {
+            Node::Image(image) => image.aspect = aspect,
+            _ => return Err(LyonTranslationError::WrongNodeKind(id)),
+        }
+        Ok(())
+    }
+
+    /// Replace the geometry of a path with a new [`Path`].
+    pub fn set_geometry(&mut self, id: NodeId, path: &Path) -> Result<(), LyonTranslationError> {
+        if !matches!(self.node_mut(id)?, Node::Path(_)) {
+            return Err(LyonTranslationError::WrongNodeKind(id));
+        }
+        let data = lyon_path_to_usvg(path).ok_or(LyonTranslationError::SvgFailure)?;
+        let hash = geometry_hash(&data);
+        let data = self.share_geometry(data, hash);
+        let Some(Node::Path(node)) = self.arena.get_mut(id) else {
+            unreachable!("checked above")
+        };
+        let old = std::mem::replace(&mut node.data, data);
+        self.release_geometry(old, 0);
+        self.invalidate_bbox();
+        Ok(())
+    }
+
+    /// Remove a node, along with its children for groups, and return it.
+    ///
+    /// A group that is still open, like the one of a [`group`](Self::group) being built, is
+    /// an [`OpenGroup`](LyonTranslationError::OpenGroup) error.
+    pub fn remove(&mut self, id: NodeId) -> Result<Node, LyonTranslationError> {
+        if self.open_groups.contains(&id) {
+            return Err(LyonTranslationError::OpenGroup(id));
+        }
+        let node = self
+            .arena
+            .remove(id)
+            .ok_or(LyonTranslationError::UnknownNode(id))?;
+        // the removed node still holds its data, once per path using it
+        let mut removed: Vec<(Arc<PathData>, usize)> = Vec::new();
+        for_each_data(&node, &mut |data| match removed
+            .iter_mut()
+            .find(|(seen, _)| Arc::ptr_eq(seen, data))
+        {
+            Some((_, uses)) => *uses += 1,
+            None => removed.push((data.clone(), 1)),
+        });
+        for (data, uses) in removed {
+            self.release_geometry(data, uses);
+        }
+        self.invalidate_bbox();
+        Ok(node)
+    }
+
+    /// Set the z-index of a node, 0 by default.
+    ///
+    /// On write, siblings are drawn by increasing z-index, in push order for equal ones.
+    /// [`nodes`](Self::nodes) keeps listing them in push order.
+    pub fn set_z_index(&mut self, id: NodeId, z_index: i32) -> Result<(), LyonTranslationError> {
+        self.arena
+            .entry_mut(id)
+            .ok_or(LyonTranslationError::UnknownNode(id))?
+            .z_index = z_index;
+        Ok(())
+    }
+
+    /// The z-index of a node, if it was not removed.
+    pub fn z_index(&self, id: NodeId) -> Option<i32> {
+        Some(self.arena.entry(id)?.z_index)
+    }
+
+    /// Draw `id` one step later, above its next sibling.
+    pub fn raise(&mut self, id: NodeId) -> Result<(), LyonTranslationError> {
+        self.reorder(id, |index, len| (index + 1).min(len - 1))
+    }
+
+    /// Draw `id` one step earlier, below its previous sibling.
+    pub fn lower(&mut self, id: NodeId) -> Result<(), LyonTranslationError> {
+        self.reorder(id, |index, _| index.saturating_sub(1))
+    }
+
+    /// Draw `id` after all its siblings.
+    pub fn raise_to_top(&mut self, id: NodeId) -> Result<(), LyonTranslationError> {
+        self.reorder(id, |_, len| len - 1)
+    }
+
+    /// Draw `id` before all its siblings.
+    pub fn lower_to_bottom(&mut self, id: NodeId) -> Result<(), LyonTranslationError> {
+        self.reorder(id, |_, _| 0)
+    }
+
+    /// Move `id` among its siblings to the position given by `to(index, len)`.
+    fn reorder(
+        &mut self,
+        id: NodeId,
+        to: impl FnOnce(usize, usize) -> usize,
+    ) -> Result<(), LyonTranslationError> {
+        let parent = self
+            .arena
+            .entry(id)
+            .ok_or(LyonTranslationError::UnknownNode(id))?
+            .parent;
+        let siblings = self.arena.siblings_mut(parent);
+        let index = siblings
+            .iter()
+            .position(|sibling| *sibling == id)
+            .expect("a node is among the children of its parent");
+        let target = to(index, siblings.len());
+        let id = siblings.remove(index);
+        siblings.insert(target, id);
+        Ok(())
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut Node, LyonTranslationError> {
+        self.arena
+            .get_mut(id)
+            .ok_or(LyonTranslationError::UnknownNode(id))
+    }
+
+    /// The group `id` while it is being built.
+    pub(crate) fn group_node_mut(&mut self, id: NodeId) -> &mut GroupNode {
+        match self.arena.get_mut(id) {
+            Some(Node::Group(group)) => group,
+            _ => unreachable!("open groups cannot be removed"),
+        }
+    }
+
+    /// Stop sharing `data` when no node of the writer uses it anymore, given the references
+    /// to it `held` outside of the writer.
+    fn release_geometry(&mut self, data: Arc<PathData>, held: usize) {
+        // one reference for `data`, one in the shared geometry
+        if Arc::strong_count(&data) > held + 2 {
+            return;
+        }
+        let hash = geometry_hash(&data);
+        if let Some(bucket) = self.geometries.get_mut(&hash) {
+            bucket.retain(|shared| !Arc::ptr_eq(shared, &data));
+            if bucket.is_empty() {
+                self.geometries.remove(&hash);
+            }
+        }
+    }
+
+    /// Whether `id` is an instance of a symbol, written as a `<use>` of the symbol.
+    fn is_instance(&self, id: NodeId) -> bool {
+        matches!(
+            self.arena.get(id),
+            Some(Node::Path(path)) if self.symbols.iter().any(|symbol| Arc::ptr_eq(&symbol.data, &path.data))
+        )
+    }
+
+    /// Grow the bounding box to contain `bbox`, unless it is to be computed again anyway.
+    pub(crate) fn grow_bbox(&mut self, bbox: Rect) {
+        if let Some(current) = self.bbox.get_mut() {
+            *current = join_bbox(*current, bbox);
+        }
+    }
+
+    /// Forget the bounding box, for when it may have shrunk. It is computed again by the
+    /// next [`bbox`](Self::bbox), so that a batch of edits only walks the nodes once.
+    pub(crate) fn invalidate_bbox(&mut self) {
+        self.bbox.take();
+    }
+}
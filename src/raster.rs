@@ -0,0 +1,31 @@
+//! Encoding of raster images pushed as pixels, see [`LyonWriter::push_rgba`].
+//!
+//! [`LyonWriter::push_rgba`]: crate::LyonWriter::push_rgba
+use crate::LyonTranslationError;
+
+/// Encode RGBA pixels, row by row with 8 bits per channel, as a PNG.
+pub(crate) fn encode_png(
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Result<Vec<u8>, LyonTranslationError> {
+    if width == 0 || height == 0 || data.len() as u64 != width as u64 * height as u64 * 4 {
+        return Err(LyonTranslationError::WrongImageData {
+            width,
+            height,
+            len: data.len(),
+        });
+    }
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(data)?;
+            writer.finish()
+        })
+        .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))?;
+    Ok(png)
+}
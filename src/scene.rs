@@ -0,0 +1,946 @@
+//! The scene model of roarsvg: nodes, paints and transforms pushed to a [`LyonWriter`].
+//!
+//! These types are owned by the crate instead of re-exported from [`usvg`], so that the
+//! public API does not change with it. They share data with [`Arc`], which also keeps
+//! [`LyonWriter`] [`Send`] and [`Sync`]. [`usvg`] only gets involved on write, to lay out
+//! text.
+//!
+//! [`LyonWriter`]: crate::LyonWriter
+use std::sync::Arc;
+
+use lyon_path::Path;
+use usvg::tiny_skia_path::Path as PathData;
+
+use crate::xml::paint_id;
+use crate::{lyon_path_to_usvg, LyonTranslationError};
+
+/// An element of the scene.
+#[derive(Clone, Debug)]
+pub enum Node {
+    Path(PathNode),
+    Image(ImageNode),
+    Text(TextNode),
+    Group(GroupNode),
+}
+
+/// A translated [`Path`] with its style.
+#[derive(Clone, Debug)]
+pub struct PathNode {
+    pub attributes: Attributes,
+    pub(crate) data: Arc<PathData>,
+    pub fill: Option<Fill>,
+    pub stroke: Option<Stroke>,
+    pub transform: Transform,
+    /// Name of a [`Style`] of the writer, see [`LyonWriter::define_style`]. If set, the path
+    /// is written with its class instead of its own `fill` and `stroke`.
+    ///
+    /// [`LyonWriter::define_style`]: crate::LyonWriter::define_style
+    pub style: Option<String>,
+}
+
+impl PathNode {
+    /// Translate a [`Path`], to be pushed with [`LyonWriter::push_node`](crate::LyonWriter::push_node)
+    /// or as part of a [`GroupNode`].
+    pub fn new(
+        path: &Path,
+        fill: Option<Fill>,
+        stroke: Option<Stroke>,
+        transform: Transform,
+    ) -> Result<Self, LyonTranslationError> {
+        Ok(Self {
+            attributes: Attributes::default(),
+            data: Arc::new(lyon_path_to_usvg(path).ok_or(LyonTranslationError::SvgFailure)?),
+            fill,
+            stroke,
+            transform,
+            style: None,
+        })
+    }
+}
+
+/// An embedded image, drawn in `rect`.
+#[derive(Clone, Debug)]
+pub struct ImageNode {
+    pub attributes: Attributes,
+    pub kind: ImageKind,
+    pub rect: Rect,
+    pub transform: Transform,
+    pub rendering_mode: ImageRendering,
+    /// How the image is fitted in `rect` when their aspect ratios differ.
+    pub aspect: AspectRatio,
+}
+
+/// The encoded data of an [`ImageNode`].
+#[derive(Clone, Debug)]
+pub enum ImageKind {
+    Png(Arc<Vec<u8>>),
+    Jpeg(Arc<Vec<u8>>),
+    Gif(Arc<Vec<u8>>),
+    Webp(Arc<Vec<u8>>),
+    /// A nested SVG document, as UTF-8.
+    Svg(Arc<Vec<u8>>),
+}
+
+impl ImageKind {
+    /// Wrap `data` encoded as `format`.
+    pub fn new(format: ImageFormat, data: Arc<Vec<u8>>) -> Self {
+        match format {
+            ImageFormat::Png => ImageKind::Png(data),
+            ImageFormat::Jpeg => ImageKind::Jpeg(data),
+            ImageFormat::Gif => ImageKind::Gif(data),
+            ImageFormat::Webp => ImageKind::Webp(data),
+            ImageFormat::Svg => ImageKind::Svg(data),
+        }
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        match self {
+            ImageKind::Png(_) => ImageFormat::Png,
+            ImageKind::Jpeg(_) => ImageFormat::Jpeg,
+            ImageKind::Gif(_) => ImageFormat::Gif,
+            ImageKind::Webp(_) => ImageFormat::Webp,
+            ImageKind::Svg(_) => ImageFormat::Svg,
+        }
+    }
+
+    pub fn data(&self) -> &Arc<Vec<u8>> {
+        match self {
+            ImageKind::Png(data)
+            | ImageKind::Jpeg(data)
+            | ImageKind::Gif(data)
+            | ImageKind::Webp(data)
+            | ImageKind::Svg(data) => data,
+        }
+    }
+}
+
+/// The encoding of the data of an image, see [`ImageFormat::detect`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Svg,
+}
+
+impl ImageFormat {
+    /// Detect the format of `data` from its first bytes, if it is supported.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(ImageFormat::Gif)
+        } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+            Some(ImageFormat::Webp)
+        } else if is_svg(data) {
+            Some(ImageFormat::Svg)
+        } else {
+            None
+        }
+    }
+
+    /// The MIME type of the format, as written in the `data:` URI of the image.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// Whether `data` looks like a SVG document: markup with a `<svg` tag near its start,
+/// after the XML declaration, comments or doctype.
+fn is_svg(data: &[u8]) -> bool {
+    let data = data.strip_prefix("\u{feff}".as_bytes()).unwrap_or(data);
+    let head = &data[..data.len().min(1024)];
+    head.iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|&b| b == b'<')
+        && head.windows(4).any(|window| window == b"<svg")
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ImageRendering {
+    #[default]
+    OptimizeQuality,
+    OptimizeSpeed,
+    /// Scaled without smoothing, each pixel drawn as a sharp square, for heatmaps and
+    /// pixel art. Written as the CSS value `pixelated`.
+    Pixelated,
+}
+
+/// How an image is fitted in its rect, as the `preserveAspectRatio` attribute.
+///
+/// The default centers the whole image, as large as it fits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AspectRatio {
+    pub align: Align,
+    /// Cover the whole rect, cropping the image, instead of fitting all of it in the rect.
+    pub slice: bool,
+}
+
+/// Where an image is aligned in its rect, see [`AspectRatio`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Align {
+    /// Stretch the image to fill the rect, ignoring its aspect ratio.
+    None,
+    XMinYMin,
+    XMidYMin,
+    XMaxYMin,
+    XMinYMid,
+    #[default]
+    XMidYMid,
+    XMaxYMid,
+    XMinYMax,
+    XMidYMax,
+    XMaxYMax,
+}
+
+/// The id, classes and `data-*` attributes of an element, to target it with CSS or
+/// JavaScript once the SVG is written, along with its tooltip, description and link.
+/// Empty values are not written.
+///
+/// Ids, classes and `data-*` names may only hold letters, digits, `-` and `_`, and not
+/// start with a digit. Others are rejected with [`LyonTranslationError::InvalidName`] when
+/// set with [`LyonWriter::set_attributes`], or on write. The ids `layer{n}`, `symbol{n}`
+/// and `geometry{n}` are generated by the writer, so they are rejected with
+/// [`LyonTranslationError::ReservedId`].
+///
+/// [`LyonWriter::set_attributes`]: crate::LyonWriter::set_attributes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes {
+    pub id: String,
+    pub classes: Vec<String>,
+    /// Pairs of name, without the `data-` prefix, and value.
+    pub data: Vec<(String, String)>,
+    /// Written as a `<title>` child, shown as a tooltip by browsers.
+    pub title: Option<String>,
+    /// Written as a `<desc>` child, for accessibility.
+    pub desc: Option<String>,
+    /// The element is wrapped in an `<a>` linking to it.
+    pub href: Option<String>,
+}
+
+impl Attributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = id.into();
+        self
+    }
+
+    pub fn with_class(mut self, class: impl Into<String>) -> Self {
+        self.classes.push(class.into());
+        self
+    }
+
+    /// Add a `data-{name}` attribute.
+    pub fn with_data(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.data.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_desc(mut self, desc: impl Into<String>) -> Self {
+        self.desc = Some(desc.into());
+        self
+    }
+
+    pub fn with_href(mut self, href: impl Into<String>) -> Self {
+        self.href = Some(href.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id.is_empty()
+            && self.classes.is_empty()
+            && self.data.is_empty()
+            && self.title.is_none()
+            && self.desc.is_none()
+            && self.href.is_none()
+    }
+
+    /// Check the id, the classes and the names of the `data-*` attributes with
+    /// [`check_name`], but not whether the id is [reserved](check_reserved).
+    pub(crate) fn check(&self) -> Result<(), LyonTranslationError> {
+        let data_names = self.data.iter().map(|(name, _)| name);
+        let id = Some(&self.id).filter(|id| !id.is_empty());
+        id.into_iter()
+            .chain(&self.classes)
+            .chain(data_names)
+            .try_for_each(|name| check_name(name))
+    }
+}
+
+/// Check that `name` can be written as a CSS class or after `data-` without escaping it.
+pub(crate) fn check_name(name: &str) -> Result<(), LyonTranslationError> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|first| !first.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(LyonTranslationError::InvalidName(name.to_string()))
+    }
+}
+
+/// Check that `id` is not of the form of the ids generated by the writer: `layer{n}`,
+/// `symbol{n}` or `geometry{n}`.
+pub(crate) fn check_reserved(id: &str) -> Result<(), LyonTranslationError> {
+    let reserved = ["layer", "symbol", "geometry"].iter().any(|prefix| {
+        id.strip_prefix(prefix)
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    });
+    if reserved {
+        Err(LyonTranslationError::ReservedId(id.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that the numbers of `node`, its descendants and their paints are finite, as SVG
+/// has no notation for NaN and the infinities.
+pub(crate) fn check_finite(node: &Node) -> Result<(), LyonTranslationError> {
+    match node {
+        Node::Path(path) => check_path_finite(path),
+        Node::Image(image) => finite(&transform_values(image.transform)),
+        Node::Text(text) => {
+            finite(&[text.font_size])?;
+            finite(&transform_values(text.transform))?;
+            check_style_finite(&text.fill, &text.stroke)
+        }
+        Node::Group(group) => {
+            finite(&[group.opacity])?;
+            finite(&transform_values(group.transform))?;
+            group.children.iter().try_for_each(check_finite)
+        }
+    }
+}
+
+/// [`check_finite`] for a single path.
+pub(crate) fn check_path_finite(path: &PathNode) -> Result<(), LyonTranslationError> {
+    // the path data is finite, or it could not have been translated
+    finite(&transform_values(path.transform))?;
+    check_style_finite(&path.fill, &path.stroke)
+}
+
+fn check_style_finite(
+    fill: &Option<Fill>,
+    stroke: &Option<Stroke>,
+) -> Result<(), LyonTranslationError> {
+    if let Some(fill) = fill {
+        finite(&[fill.opacity])?;
+        check_paint_finite(&fill.paint)?;
+    }
+    if let Some(stroke) = stroke {
+        finite(&[
+            stroke.dashoffset,
+            stroke.miterlimit,
+            stroke.opacity,
+            stroke.width,
+        ])?;
+        finite(stroke.dasharray.as_deref().unwrap_or_default())?;
+        check_paint_finite(&stroke.paint)?;
+    }
+    Ok(())
+}
+
+fn check_paint_finite(paint: &Paint) -> Result<(), LyonTranslationError> {
+    let stops = |stops: &[Stop]| {
+        let values: Vec<f32> = stops.iter().flat_map(|s| [s.offset, s.opacity]).collect();
+        finite(&values)
+    };
+    match paint {
+        Paint::Color(_) => Ok(()),
+        Paint::LinearGradient(lg) => {
+            finite(&[lg.x1, lg.y1, lg.x2, lg.y2])?;
+            finite(&transform_values(lg.transform))?;
+            stops(&lg.stops)
+        }
+        Paint::RadialGradient(rg) => {
+            finite(&[rg.cx, rg.cy, rg.r, rg.fx, rg.fy])?;
+            finite(&transform_values(rg.transform))?;
+            stops(&rg.stops)
+        }
+        Paint::Pattern(pattern) => {
+            finite(&transform_values(pattern.transform))?;
+            pattern.children.iter().try_for_each(check_finite)
+        }
+    }
+}
+
+fn transform_values(ts: Transform) -> [f32; 6] {
+    [ts.sx, ts.ky, ts.kx, ts.sy, ts.tx, ts.ty]
+}
+
+fn finite(values: &[f32]) -> Result<(), LyonTranslationError> {
+    match values.iter().find(|value| !value.is_finite()) {
+        Some(value) => Err(LyonTranslationError::NotFinite(*value)),
+        None => Ok(()),
+    }
+}
+
+/// Check the ids and names of `node`, its descendants, their paint servers and the
+/// children of their patterns.
+pub(crate) fn check_names(node: &Node) -> Result<(), LyonTranslationError> {
+    node.attributes().check()?;
+    // the writer numbers the layers itself
+    if !matches!(node, Node::Group(group) if group.layer.is_some()) {
+        check_reserved(&node.attributes().id)?;
+    }
+    match node {
+        Node::Path(path) => {
+            if let Some(ref class) = path.style {
+                check_name(class)?;
+            }
+            let fill = path.fill.iter().map(|f| &f.paint);
+            for paint in fill.chain(path.stroke.iter().map(|s| &s.paint)) {
+                if let Some(id) = paint_id(paint) {
+                    check_name(id)?;
+                    check_reserved(id)?;
+                }
+                if let Paint::Pattern(pattern) = paint {
+                    pattern.children.iter().try_for_each(check_names)?;
+                }
+            }
+            Ok(())
+        }
+        Node::Group(group) => group.children.iter().try_for_each(check_names),
+        Node::Image(_) | Node::Text(_) => Ok(()),
+    }
+}
+
+/// A single line of text, converted to paths on write.
+#[derive(Clone, Debug)]
+pub struct TextNode {
+    pub text: String,
+    pub font_families: Vec<String>,
+    pub font_size: f32,
+    pub transform: Transform,
+    pub fill: Option<Fill>,
+    pub stroke: Option<Stroke>,
+    pub dominant_baseline: DominantBaseline,
+    /// Written on the group holding the glyphs.
+    pub attributes: Attributes,
+}
+
+/// A set of nodes sharing a transform, opacity and blending.
+#[derive(Clone, Debug)]
+pub struct GroupNode {
+    pub attributes: Attributes,
+    pub transform: Transform,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub isolate: bool,
+    /// Hidden groups are written with `display:none`.
+    pub visible: bool,
+    /// Label of the group if it is a layer, see [`LyonWriter::layer`](crate::LyonWriter::layer).
+    pub layer: Option<String>,
+    pub children: Vec<Node>,
+}
+
+impl Default for GroupNode {
+    fn default() -> Self {
+        Self {
+            attributes: Attributes::default(),
+            transform: Transform::default(),
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            isolate: false,
+            visible: true,
+            layer: None,
+            children: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    pub const fn new_rgb(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    pub const fn black() -> Self {
+        Self::new_rgb(0, 0, 0)
+    }
+
+    pub const fn white() -> Self {
+        Self::new_rgb(255, 255, 255)
+    }
+}
+
+/// How a fill or a stroke is painted: a plain color or a paint server of the `<defs>`.
+#[derive(Clone, Debug)]
+pub enum Paint {
+    Color(Color),
+    LinearGradient(Arc<LinearGradient>),
+    RadialGradient(Arc<RadialGradient>),
+    Pattern(Arc<Pattern>),
+}
+
+impl PartialEq for Paint {
+    /// Patterns hold nodes, so they are equal only if they are the same `Arc`.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Color(a), Self::Color(b)) => a == b,
+            (Self::LinearGradient(a), Self::LinearGradient(b)) => a == b,
+            (Self::RadialGradient(a), Self::RadialGradient(b)) => a == b,
+            (Self::Pattern(a), Self::Pattern(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// A `<linearGradient>`, referenced by its `id`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinearGradient {
+    pub id: String,
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub units: Units,
+    pub transform: Transform,
+    pub spread_method: SpreadMethod,
+    pub stops: Vec<Stop>,
+}
+
+/// A `<radialGradient>`, referenced by its `id`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RadialGradient {
+    pub id: String,
+    pub cx: f32,
+    pub cy: f32,
+    pub r: f32,
+    pub fx: f32,
+    pub fy: f32,
+    pub units: Units,
+    pub transform: Transform,
+    pub spread_method: SpreadMethod,
+    pub stops: Vec<Stop>,
+}
+
+/// A `<pattern>`, referenced by its `id`, tiling its children over `rect`.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pub id: String,
+    /// The units of `rect`.
+    pub units: Units,
+    /// The units of the children, `UserSpaceOnUse` unless they are relative to the
+    /// bounding box of the painted element.
+    pub content_units: Units,
+    pub transform: Transform,
+    /// The tile, repeated in both directions.
+    pub rect: Rect,
+    pub children: Vec<Node>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {
+    /// Between 0 and 1.
+    pub offset: f32,
+    pub color: Color,
+    pub opacity: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Units {
+    UserSpaceOnUse,
+    #[default]
+    ObjectBoundingBox,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SpreadMethod {
+    #[default]
+    Pad,
+    Reflect,
+    Repeat,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub paint: Paint,
+    /// Between 0 and 1.
+    pub opacity: f32,
+    pub rule: FillRule,
+}
+
+impl Default for Fill {
+    fn default() -> Self {
+        Self {
+            paint: Paint::Color(Color::black()),
+            opacity: 1.0,
+            rule: FillRule::NonZero,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+/// Description of the whole document, see
+/// [`LyonWriter::with_metadata`](crate::LyonWriter::with_metadata).
+///
+/// The `title` and `desc` are written as the first children of the `<svg>`, which is then
+/// marked as an image labelled by them for assistive technologies. The provenance fields
+/// are written as RDF with Dublin Core terms in a `<metadata>` element.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub desc: Option<String>,
+    /// Language of the texts of the document, as a BCP 47 tag like `en-GB`.
+    pub lang: Option<String>,
+    pub creator: Option<String>,
+    /// Preferably in ISO 8601 format, like `2024-03-01`.
+    pub date: Option<String>,
+    /// URL of the license, like `https://creativecommons.org/licenses/by/4.0/`.
+    pub license: Option<String>,
+    /// The dataset or resource the document is derived from.
+    pub source: Option<String>,
+}
+
+impl Metadata {
+    pub(crate) fn has_provenance(&self) -> bool {
+        self.creator.is_some()
+            || self.date.is_some()
+            || self.license.is_some()
+            || self.source.is_some()
+    }
+}
+
+/// A fill and a stroke shared by paths through a CSS class, see
+/// [`LyonWriter::define_style`](crate::LyonWriter::define_style).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub fill: Option<Fill>,
+    pub stroke: Option<Stroke>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub paint: Paint,
+    pub dasharray: Option<Vec<f32>>,
+    pub dashoffset: f32,
+    pub miterlimit: f32,
+    /// Between 0 and 1.
+    pub opacity: f32,
+    pub width: f32,
+    pub linecap: LineCap,
+    pub linejoin: LineJoin,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Self {
+            paint: Paint::Color(Color::black()),
+            dasharray: None,
+            dashoffset: 0.0,
+            miterlimit: 4.0,
+            opacity: 1.0,
+            width: 1.0,
+            linecap: LineCap::Butt,
+            linejoin: LineJoin::Miter,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LineCap {
+    #[default]
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LineJoin {
+    #[default]
+    Miter,
+    MiterClip,
+    Round,
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DominantBaseline {
+    #[default]
+    Auto,
+    UseScript,
+    NoChange,
+    ResetSize,
+    Ideographic,
+    Alphabetic,
+    Hanging,
+    Mathematical,
+    Central,
+    Middle,
+    TextAfterEdge,
+    TextBeforeEdge,
+}
+
+/// An affine transform, as the SVG `matrix(sx ky kx sy tx ty)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub sx: f32,
+    pub kx: f32,
+    pub ky: f32,
+    pub sy: f32,
+    pub tx: f32,
+    pub ty: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub const fn identity() -> Self {
+        Self::from_row(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+
+    pub const fn from_row(sx: f32, ky: f32, kx: f32, sy: f32, tx: f32, ty: f32) -> Self {
+        Self {
+            sx,
+            kx,
+            ky,
+            sy,
+            tx,
+            ty,
+        }
+    }
+
+    pub const fn from_translate(tx: f32, ty: f32) -> Self {
+        Self::from_row(1.0, 0.0, 0.0, 1.0, tx, ty)
+    }
+
+    pub const fn from_scale(sx: f32, sy: f32) -> Self {
+        Self::from_row(sx, 0.0, 0.0, sy, 0.0, 0.0)
+    }
+
+    pub const fn from_skew(kx: f32, ky: f32) -> Self {
+        Self::from_row(1.0, ky, kx, 1.0, 0.0, 0.0)
+    }
+
+    /// Rotation by `angle` degrees around the origin.
+    pub fn from_rotate(angle: f32) -> Self {
+        Self::from_usvg(usvg::Transform::from_rotate(angle))
+    }
+
+    /// Rotation by `angle` degrees around `(tx, ty)`.
+    pub fn from_rotate_at(angle: f32, tx: f32, ty: f32) -> Self {
+        Self::from_usvg(usvg::Transform::from_rotate_at(angle, tx, ty))
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::identity()
+    }
+
+    /// Apply `other` before `self`.
+    pub fn pre_concat(&self, other: Self) -> Self {
+        Self::from_usvg(self.to_usvg().pre_concat(other.to_usvg()))
+    }
+
+    /// Apply `other` after `self`.
+    pub fn post_concat(&self, other: Self) -> Self {
+        Self::from_usvg(self.to_usvg().post_concat(other.to_usvg()))
+    }
+
+    pub fn pre_translate(&self, tx: f32, ty: f32) -> Self {
+        self.pre_concat(Self::from_translate(tx, ty))
+    }
+
+    pub fn post_translate(&self, tx: f32, ty: f32) -> Self {
+        self.post_concat(Self::from_translate(tx, ty))
+    }
+
+    pub fn pre_scale(&self, sx: f32, sy: f32) -> Self {
+        self.pre_concat(Self::from_scale(sx, sy))
+    }
+
+    pub fn post_scale(&self, sx: f32, sy: f32) -> Self {
+        self.post_concat(Self::from_scale(sx, sy))
+    }
+
+    pub fn invert(&self) -> Option<Self> {
+        self.to_usvg().invert().map(Self::from_usvg)
+    }
+
+    pub(crate) fn to_usvg(self) -> usvg::Transform {
+        usvg::Transform::from_row(self.sx, self.ky, self.kx, self.sy, self.tx, self.ty)
+    }
+
+    pub(crate) fn from_usvg(ts: usvg::Transform) -> Self {
+        Self::from_row(ts.sx, ts.ky, ts.kx, ts.sy, ts.tx, ts.ty)
+    }
+}
+
+/// An axis-aligned rectangle with a non-negative size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    left: f32,
+    top: f32,
+    right: f32,
+    bottom: f32,
+}
+
+impl Rect {
+    /// Returns `None` for negative or non-finite sizes.
+    pub fn from_xywh(x: f32, y: f32, width: f32, height: f32) -> Option<Self> {
+        Self::from_ltrb(x, y, x + width, y + height)
+    }
+
+    /// Returns `None` if `right < left`, `bottom < top` or any side is not finite.
+    pub fn from_ltrb(left: f32, top: f32, right: f32, bottom: f32) -> Option<Self> {
+        let finite = [left, top, right, bottom].iter().all(|v| v.is_finite());
+        (finite && left <= right && top <= bottom).then_some(Self {
+            left,
+            top,
+            right,
+            bottom,
+        })
+    }
+
+    pub fn x(&self) -> f32 {
+        self.left
+    }
+
+    pub fn y(&self) -> f32 {
+        self.top
+    }
+
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f32 {
+        self.bottom - self.top
+    }
+
+    pub fn left(&self) -> f32 {
+        self.left
+    }
+
+    pub fn top(&self) -> f32 {
+        self.top
+    }
+
+    pub fn right(&self) -> f32 {
+        self.right
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.bottom
+    }
+
+    pub(crate) fn to_usvg(self) -> Option<usvg::Rect> {
+        usvg::Rect::from_ltrb(self.left, self.top, self.right, self.bottom)
+    }
+
+    pub(crate) fn from_usvg(rect: usvg::Rect) -> Self {
+        Self {
+            left: rect.left(),
+            top: rect.top(),
+            right: rect.right(),
+            bottom: rect.bottom(),
+        }
+    }
+}
+
+impl Node {
+    pub fn attributes(&self) -> &Attributes {
+        match self {
+            Node::Path(path) => &path.attributes,
+            Node::Image(image) => &image.attributes,
+            Node::Text(text) => &text.attributes,
+            Node::Group(group) => &group.attributes,
+        }
+    }
+
+    pub fn attributes_mut(&mut self) -> &mut Attributes {
+        match self {
+            Node::Path(path) => &mut path.attributes,
+            Node::Image(image) => &mut image.attributes,
+            Node::Text(text) => &mut text.attributes,
+            Node::Group(group) => &mut group.attributes,
+        }
+    }
+
+    /// Copy the paths of a [`usvg::Node`] holding text laid out by [`usvg`].
+    ///
+    /// The glyphs take the `fill` and `stroke` of the text, where they have one.
+    pub(crate) fn from_laid_out_text(
+        node: &usvg::Node,
+        fill: &Option<Fill>,
+        stroke: &Option<Stroke>,
+    ) -> Option<Self> {
+        match *node.borrow() {
+            usvg::NodeKind::Path(ref path) => Some(Node::Path(PathNode {
+                attributes: Attributes::default(),
+                data: Arc::new((*path.data).clone()),
+                fill: path.fill.as_ref().and(fill.clone()),
+                stroke: path.stroke.as_ref().and(stroke.clone()),
+                transform: Transform::from_usvg(path.transform),
+                style: None,
+            })),
+            usvg::NodeKind::Group(ref group) => Some(Node::Group(GroupNode {
+                transform: Transform::from_usvg(group.transform),
+                children: node
+                    .children()
+                    .filter_map(|child| Self::from_laid_out_text(&child, fill, stroke))
+                    .collect(),
+                ..Default::default()
+            })),
+            usvg::NodeKind::Image(_) | usvg::NodeKind::Text(_) => None,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Snapshot testing of the written SVGs, relying on the output being reproducible.
+use std::path::Path;
+
+/// Environment variable that makes [`assert_snapshot`] overwrite the snapshots.
+const UPDATE_VAR: &str = "ROARSVG_UPDATE_SNAPSHOTS";
+
+/// Check that `svg` is the same as the snapshot stored at `snapshot_path`.
+///
+/// The snapshot is created if it does not exist yet, and overwritten if the
+/// `ROARSVG_UPDATE_SNAPSHOTS` environment variable is set. Otherwise, this panics on the
+/// first line that differs.
+///
+/// # Example
+///
+/// ```
+/// use roarsvg::{assert_snapshot, Color, LyonWriter, fill};
+/// use lyon_path::Path;
+/// use lyon_path::geom::euclid::Point2D;
+///
+/// let mut writer = LyonWriter::new();
+/// let mut path_builder = Path::builder();
+/// path_builder.begin(Point2D::origin());
+/// path_builder.line_to(Point2D::new(1.0, 1.0));
+/// path_builder.line_to(Point2D::new(0.0, 1.0));
+/// path_builder.end(true);
+/// writer
+///     .push(&path_builder.build(), Some(fill(Color::black(), 1.0)), None, None)
+///     .expect("Path should be writable!");
+/// let svg = writer.to_svg_string().expect("Writing should not panic!");
+/// assert_snapshot(&svg, "triangle.svg");
+///
+/// # std::fs::remove_file("triangle.svg").unwrap();
+/// ```
+pub fn assert_snapshot<P: AsRef<Path>>(svg: &str, snapshot_path: P) {
+    let snapshot_path = snapshot_path.as_ref();
+    let update = std::env::var_os(UPDATE_VAR).is_some();
+    let expected = match std::fs::read_to_string(snapshot_path) {
+        Ok(expected) if !update => expected,
+        _ => {
+            if let Some(dir) = snapshot_path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+            {
+                std::fs::create_dir_all(dir).expect("the snapshot directory should be writable");
+            }
+            std::fs::write(snapshot_path, svg).expect("the snapshot should be writable");
+            return;
+        }
+    };
+    if expected == svg {
+        return;
+    }
+    let lines = expected.lines().count().max(svg.lines().count());
+    let (line, (expected_line, actual_line)) = expected
+        .lines()
+        .chain(std::iter::repeat(""))
+        .zip(svg.lines().chain(std::iter::repeat("")))
+        .take(lines)
+        .enumerate()
+        .find(|(_, (expected, actual))| expected != actual)
+        // only the line endings differ
+        .unwrap_or((lines, ("", "")));
+    panic!(
+        "the SVG differs from the snapshot {} at line {}:\n  expected: {expected_line}\n  actual:   {actual_line}\nSet {UPDATE_VAR} to update it.",
+        snapshot_path.display(),
+        line + 1,
+    );
+}
@@ -0,0 +1,220 @@
+//! Incremental SVG writing, for scenes too large to keep in memory.
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::scene::{check_finite, check_path_finite, Paint};
+use crate::xml::{add_paint_server, paint_id, path_paints, Serializer, WriteOptions};
+use crate::{
+    create_png_node, join_bbox, lyon_path_to_svg_with_attributes, node_bbox, path_bbox, Fill,
+    LyonTranslationError, Rect, Stroke, SvgTransform,
+};
+use lyon_path::Path;
+
+/// Write [`Path`]s to an SVG as they are pushed, instead of keeping them until
+/// [`LyonWriter::write`](crate::LyonWriter::write).
+///
+/// Memory stays bounded regardless of the number of paths, at the cost of fixing the
+/// viewBox up front. If it is not known beforehand, run the same pushes into a writer
+/// over [`std::io::sink`] and use its [`bbox`](Self::bbox) for the real one.
+///
+/// The document is complete once [`finish`](Self::finish) returns. Dropping the writer
+/// also closes it, but the errors of the last writes are then lost.
+///
+/// # Example
+///
+/// ```
+/// use roarsvg::{Color, LyonStreamWriter, stroke};
+/// use lyon_path::Path;
+/// use lyon_path::geom::euclid::Point2D;
+///
+/// fn segments<W: std::io::Write>(writer: &mut LyonStreamWriter<W>) {
+///     for i in 0..1000 {
+///         let mut path_builder = Path::builder();
+///         path_builder.begin(Point2D::new(i as f32, 0.0));
+///         path_builder.line_to(Point2D::new(i as f32 + 1.0, (i as f32).sqrt()));
+///         path_builder.end(false);
+///         writer
+///             .push(
+///                 &path_builder.build(),
+///                 None,
+///                 Some(stroke(Color::black(), 1.0, 0.1)),
+///                 None,
+///             )
+///             .expect("Segment should be writable!");
+///     }
+/// }
+/// // first pass, only to measure the extent of the scene
+/// let mut dry_run = LyonStreamWriter::new(std::io::sink(), 0.0, 0.0, 1.0, 1.0).unwrap();
+/// segments(&mut dry_run);
+/// let bbox = dry_run.bbox().unwrap();
+///
+/// let file = std::fs::File::create("stream.svg").unwrap();
+/// let mut writer = LyonStreamWriter::new(
+///     std::io::BufWriter::new(file),
+///     bbox.x(),
+///     bbox.y(),
+///     bbox.width(),
+///     bbox.height(),
+/// )
+/// .unwrap();
+/// segments(&mut writer);
+/// writer.finish().expect("Writing should not panic!");
+///
+/// # std::fs::remove_file("stream.svg").unwrap();
+/// ```
+pub struct LyonStreamWriter<W: Write> {
+    /// Taken by [`finish`](Self::finish), otherwise the document is closed on drop.
+    serializer: Option<Serializer<'static, W>>,
+    bbox: Option<Rect>,
+    transform: SvgTransform,
+    /// Ids of the paint servers already written to a `<defs>`.
+    paint_servers: HashSet<String>,
+}
+
+impl<W: Write> LyonStreamWriter<W> {
+    /// Start writing a SVG to `out` whose viewBox is `(min_x, min_y, width, height)`.
+    pub fn new(
+        out: W,
+        min_x: f32,
+        min_y: f32,
+        width: f32,
+        height: f32,
+    ) -> Result<Self, LyonTranslationError> {
+        Self::with_transform(out, min_x, min_y, width, height, SvgTransform::identity())
+    }
+
+    /// Like [`new`](Self::new), applying a [`SvgTransform`] to the whole SVG as a group.
+    pub fn with_transform(
+        out: W,
+        min_x: f32,
+        min_y: f32,
+        width: f32,
+        height: f32,
+        transform: SvgTransform,
+    ) -> Result<Self, LyonTranslationError> {
+        Self::with_write_options(
+            out,
+            min_x,
+            min_y,
+            width,
+            height,
+            transform,
+            WriteOptions::default(),
+        )
+    }
+
+    /// Like [`with_transform`](Self::with_transform), formatting the SVG with `options`
+    /// as [`LyonWriter::with_write_options`](crate::LyonWriter::with_write_options) does.
+    pub fn with_write_options(
+        out: W,
+        min_x: f32,
+        min_y: f32,
+        width: f32,
+        height: f32,
+        transform: SvgTransform,
+        options: WriteOptions,
+    ) -> Result<Self, LyonTranslationError> {
+        let wrong_bbox = || LyonTranslationError::WrongBoundingBox {
+            min_x,
+            max_x: min_x + width,
+            min_y,
+            max_y: min_y + height,
+        };
+        let view_box = Rect::from_xywh(min_x, min_y, width, height)
+            .filter(|rect| rect.width() > 0.0 && rect.height() > 0.0)
+            .ok_or_else(wrong_bbox)?;
+        let mut serializer = Serializer::new(out, &[], options);
+        serializer
+            .start_svg(view_box, true, false)
+            .and_then(|_| serializer.start_group(transform))
+            .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))?;
+        Ok(Self {
+            serializer: Some(serializer),
+            bbox: None,
+            transform,
+            paint_servers: HashSet::new(),
+        })
+    }
+
+    /// Translate a [`Path`] and write it right away.
+    pub fn push(
+        &mut self,
+        path: &Path,
+        fill: Option<Fill>,
+        stroke: Option<Stroke>,
+        transform: Option<SvgTransform>,
+    ) -> Result<(), LyonTranslationError> {
+        let path = lyon_path_to_svg_with_attributes(path, fill, stroke, transform)?;
+        check_path_finite(&path)?;
+        if let Some(bbox) = path_bbox(&path.data, path.transform.post_concat(self.transform)) {
+            self.bbox = join_bbox(self.bbox, bbox);
+        }
+        let mut new_paints: Vec<Paint> = Vec::new();
+        for paint in path_paints(&path) {
+            add_paint_server(paint, &mut new_paints);
+        }
+        new_paints.retain(|paint| {
+            paint_id(paint).is_some_and(|id| self.paint_servers.insert(id.clone()))
+        });
+        let serializer = self.serializer();
+        let result = if new_paints.is_empty() {
+            Ok(())
+        } else {
+            serializer.write_paint_servers(&new_paints)
+        };
+        result
+            .and_then(|_| serializer.write_path(&path, true))
+            .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))
+    }
+
+    /// Write a raster image (formatted by the caller) as a PNG right away.
+    pub fn push_png(
+        &mut self,
+        data: &[u8],
+        transform: SvgTransform,
+        width: f32,
+        height: f32,
+    ) -> Result<(), LyonTranslationError> {
+        let node = create_png_node(data, transform, width, height)?;
+        check_finite(&node)?;
+        if let Some(bbox) = node_bbox(&node, self.transform) {
+            self.bbox = join_bbox(self.bbox, bbox);
+        }
+        self.serializer()
+            .write_node(&node)
+            .map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))
+    }
+
+    /// Exact bounding box of everything written so far, in the coordinates of the viewBox.
+    pub fn bbox(&self) -> Option<Rect> {
+        self.bbox
+    }
+
+    /// Close the SVG document and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, LyonTranslationError> {
+        let serializer = self.serializer.take().expect("only taken by finish");
+        close(serializer).map_err(|e| LyonTranslationError::IoWrite(Box::new(e)))
+    }
+
+    fn serializer(&mut self) -> &mut Serializer<'static, W> {
+        self.serializer.as_mut().expect("only taken by finish")
+    }
+}
+
+impl<W: Write> Drop for LyonStreamWriter<W> {
+    fn drop(&mut self) {
+        if let Some(serializer) = self.serializer.take() {
+            // nobody is left to handle the error
+            let _ = close(serializer);
+        }
+    }
+}
+
+/// Close the root group and the `<svg>` element, then flush.
+fn close<W: Write>(mut serializer: Serializer<'static, W>) -> std::io::Result<W> {
+    serializer.end()?;
+    serializer.end()?;
+    let mut out = serializer.into_inner();
+    out.flush()?;
+    Ok(out)
+}
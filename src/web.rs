@@ -0,0 +1,116 @@
+//! Outputs for wasm32, to hand the SVG to JavaScript instead of downloading it.
+//!
+//! [`write`](LyonWriter::write) still downloads the SVG on wasm32, which
+//! [`download`](LyonWriter::download) also does without the file options.
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, BlobPropertyBag, Element, HtmlElement, Url};
+
+use crate::{FontProvider, LyonTranslationError, LyonWriter, NoText};
+
+const SVG_MIME: &str = "image/svg+xml";
+
+pub(crate) fn js_error(context: &str, e: JsValue) -> LyonTranslationError {
+    LyonTranslationError::IoWrite(format!("{context}: {e:?}").into())
+}
+
+/// The bytes of `data`, copied to JavaScript memory.
+fn uint8_array(data: &[u8]) -> Uint8Array {
+    Uint8Array::from(data)
+}
+
+/// A [`Blob`] of `data` with the given MIME type.
+pub(crate) fn blob(data: &[u8], mime: &str) -> Result<Blob, LyonTranslationError> {
+    let options = BlobPropertyBag::new();
+    options.set_type(mime);
+    Blob::new_with_u8_array_sequence_and_options(&Array::of1(&uint8_array(data)), &options)
+        .map_err(|e| js_error("error writing blob", e))
+}
+
+fn object_url(data: &[u8], mime: &str) -> Result<String, LyonTranslationError> {
+    Url::create_object_url_with_blob(&blob(data, mime)?)
+        .map_err(|e| js_error("error writing url", e))
+}
+
+/// Replace the children of `element` by the SVG.
+fn insert(svg: &str, element: &Element) {
+    element.set_inner_html(svg);
+}
+
+/// Make the browser download `data` as `file_name`, through a clicked `<a download>`.
+pub(crate) fn download(data: &[u8], file_name: &str) -> Result<(), LyonTranslationError> {
+    let url = object_url(data, SVG_MIME)?;
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| LyonTranslationError::IoWrite("no document to download from".into()))?;
+    let link = document
+        .create_element("a")
+        .map_err(|e| js_error("error creating <a>", e))?;
+    link.set_attribute("href", &url)
+        .map_err(|e| js_error("error creating <href>", e))?;
+    link.set_attribute("download", file_name)
+        .map_err(|e| js_error("invalid filename", e))?;
+    link.dyn_into::<HtmlElement>()
+        .map_err(|_| LyonTranslationError::IoWrite("error creating <html>".into()))?
+        .click();
+    Url::revoke_object_url(&url).map_err(|e| js_error("error revoking url", e))
+}
+
+impl LyonWriter<NoText> {
+    /// The SVG as UTF-8 bytes in a [`Uint8Array`], to pass it to JavaScript.
+    pub fn to_uint8_array(self) -> Result<Uint8Array, LyonTranslationError> {
+        Ok(uint8_array(self.to_svg_string()?.as_bytes()))
+    }
+
+    /// The SVG in a [`Blob`] of type `image/svg+xml`, to upload it for instance.
+    pub fn to_blob(self) -> Result<Blob, LyonTranslationError> {
+        blob(self.to_svg_string()?.as_bytes(), SVG_MIME)
+    }
+
+    /// An object URL to a [`Blob`] of the SVG, to show it in an `<img>` for instance.
+    ///
+    /// The URL keeps the SVG in memory until it is revoked with
+    /// [`Url::revoke_object_url`].
+    pub fn to_object_url(self) -> Result<String, LyonTranslationError> {
+        object_url(self.to_svg_string()?.as_bytes(), SVG_MIME)
+    }
+
+    /// Show the SVG inline, replacing the children of `element`.
+    pub fn insert_into(self, element: &Element) -> Result<(), LyonTranslationError> {
+        insert(&self.to_svg_string()?, element);
+        Ok(())
+    }
+
+    /// Make the browser download the SVG as `file_name`.
+    pub fn download(self, file_name: &str) -> Result<(), LyonTranslationError> {
+        download(self.to_svg_string()?.as_bytes(), file_name)
+    }
+}
+
+impl<T: FontProvider> LyonWriter<Option<T>> {
+    /// The SVG as UTF-8 bytes in a [`Uint8Array`], to pass it to JavaScript.
+    pub fn to_uint8_array(self) -> Result<Uint8Array, LyonTranslationError> {
+        Ok(uint8_array(self.to_svg_string()?.as_bytes()))
+    }
+
+    /// The SVG in a [`Blob`] of type `image/svg+xml`.
+    pub fn to_blob(self) -> Result<Blob, LyonTranslationError> {
+        blob(self.to_svg_string()?.as_bytes(), SVG_MIME)
+    }
+
+    /// An object URL to a [`Blob`] of the SVG, to be revoked with [`Url::revoke_object_url`].
+    pub fn to_object_url(self) -> Result<String, LyonTranslationError> {
+        object_url(self.to_svg_string()?.as_bytes(), SVG_MIME)
+    }
+
+    /// Show the SVG inline, replacing the children of `element`.
+    pub fn insert_into(self, element: &Element) -> Result<(), LyonTranslationError> {
+        insert(&self.to_svg_string()?, element);
+        Ok(())
+    }
+
+    /// Make the browser download the SVG as `file_name`.
+    pub fn download(self, file_name: &str) -> Result<(), LyonTranslationError> {
+        download(self.to_svg_string()?.as_bytes(), file_name)
+    }
+}
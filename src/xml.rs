@@ -0,0 +1,1082 @@
+//! SVG serialization of the nodes of a [`LyonWriter`](crate::LyonWriter).
+//!
+//! [`usvg`] can write its own [`Tree`](usvg::Tree), but only the elements the tree can
+//! represent. Writing it here lets us emit elements that [`usvg`] resolves away, like
+//! `<symbol>`/`<use>`.
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use usvg::tiny_skia_path::{Path as PathData, PathSegment, Point};
+use usvg::ApproxZeroUlps;
+
+use crate::scene::{
+    Align, AspectRatio, Attributes, BlendMode, Color, Fill, FillRule, GroupNode, ImageKind,
+    ImageNode, ImageRendering, LineCap, LineJoin, Metadata, Node, Paint, PathNode, Rect,
+    SpreadMethod, Stop, Stroke, Style, Transform, Units,
+};
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+const INKSCAPE_NS: &str = "http://www.inkscape.org/namespaces/inkscape";
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const CC_NS: &str = "http://creativecommons.org/ns#";
+const PRECISION: u8 = 8;
+
+/// How the SVG is formatted, see
+/// [`LyonWriter::with_write_options`](crate::LyonWriter::with_write_options).
+///
+/// The default output is indented by 4 spaces with double-quoted attributes, absolute path
+/// commands, 8 decimals, no XML declaration and no id prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Decimals kept for coordinates and lengths, like path data and stroke widths.
+    pub coordinates_precision: u8,
+    /// Decimals kept for the values of transform matrices.
+    pub transforms_precision: u8,
+    pub indent: Indent,
+    pub quotes: AttributeQuotes,
+    /// Write path data with relative commands, which is usually shorter.
+    pub relative_paths: bool,
+    /// Start with `<?xml version="1.0" encoding="UTF-8"?>`.
+    pub xml_declaration: bool,
+    /// Prepended to every id of the document, and to the references to them, so that
+    /// several SVGs can be inlined in the same HTML page without their ids colliding.
+    pub id_prefix: String,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            coordinates_precision: PRECISION,
+            transforms_precision: PRECISION,
+            indent: Indent::Spaces(4),
+            quotes: AttributeQuotes::Double,
+            relative_paths: false,
+            xml_declaration: false,
+            id_prefix: String::new(),
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Everything on a single line, with relative path commands and 3 decimals.
+    pub fn minified() -> Self {
+        Self {
+            coordinates_precision: 3,
+            transforms_precision: 3,
+            indent: Indent::None,
+            relative_paths: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Indentation of the nested elements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Indent {
+    /// No line breaks between elements, for minified output.
+    None,
+    Spaces(u8),
+    Tabs,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AttributeQuotes {
+    #[default]
+    Double,
+    Single,
+}
+
+/// Serialize `nodes` to a SVG string.
+///
+/// Paths whose data is shared with one of the `symbols` are written as `<use>` elements
+/// pointing to a `<symbol>` with the id `symbol{index}`. Path data shared by several other
+/// paths is written once as a `<path>` with the id `geometry{index}` (numbered in order of
+/// appearance) and referenced by `<use>` elements that carry the fill, stroke and transform.
+/// [`Text`](Node::Text) nodes are skipped, they must be converted to paths beforehand.
+///
+/// The `styles` are written as CSS classes in a `<style>` element, paths with a
+/// [`style`](PathNode::style) take its class instead of their own fill and stroke.
+pub(crate) fn to_string(
+    view_box: Rect,
+    nodes: &[Node],
+    symbols: &[PathNode],
+    styles: &[(String, Style)],
+    metadata: &Metadata,
+    options: WriteOptions,
+) -> String {
+    let mut serializer = Serializer::new(Vec::new(), symbols, options);
+    serializer
+        .write_document(view_box, nodes, styles, metadata)
+        .expect("writing to a Vec cannot fail");
+    String::from_utf8(serializer.into_inner()).expect("the written SVG is valid UTF-8")
+}
+
+/// Writes the SVG elements of the nodes.
+pub(crate) struct Serializer<'a, W: Write> {
+    xml: XmlWriter<W>,
+    options: WriteOptions,
+    symbols: &'a [PathNode],
+    symbol_ids: HashMap<*const PathData, usize>,
+    /// Path data referenced by more than one path, in order of appearance.
+    geometries: Vec<Arc<PathData>>,
+    geometry_ids: HashMap<*const PathData, usize>,
+}
+
+impl<'a, W: Write> Serializer<'a, W> {
+    pub(crate) fn new(out: W, symbols: &'a [PathNode], options: WriteOptions) -> Self {
+        Self {
+            xml: XmlWriter::new(out, options.indent, options.quotes),
+            options,
+            symbols,
+            symbol_ids: symbols
+                .iter()
+                .enumerate()
+                .map(|(i, symbol)| (Arc::as_ptr(&symbol.data), i))
+                .collect(),
+            geometries: Vec::new(),
+            geometry_ids: HashMap::new(),
+        }
+    }
+
+    /// Find the path data that is shared by several paths (other than symbol instances).
+    fn collect_shared_geometry(&mut self, nodes: &[Node], seen: &mut HashSet<*const PathData>) {
+        for node in nodes {
+            match node {
+                Node::Path(path) => {
+                    let ptr = Arc::as_ptr(&path.data);
+                    if self.symbol_ids.contains_key(&ptr) || self.geometry_ids.contains_key(&ptr) {
+                        continue;
+                    }
+                    if !seen.insert(ptr) {
+                        self.geometry_ids.insert(ptr, self.geometries.len());
+                        self.geometries.push(path.data.clone());
+                    }
+                }
+                Node::Group(group) => self.collect_shared_geometry(&group.children, seen),
+                Node::Image(_) | Node::Text(_) => {}
+            }
+        }
+    }
+
+    fn write_document(
+        &mut self,
+        view_box: Rect,
+        nodes: &[Node],
+        styles: &[(String, Style)],
+        metadata: &Metadata,
+    ) -> io::Result<()> {
+        self.collect_shared_geometry(nodes, &mut HashSet::new());
+        let xlink = !self.symbols.is_empty() || !self.geometries.is_empty() || has_xlinks(nodes);
+        self.start_svg(view_box, xlink, has_layers(nodes))?;
+        self.write_metadata(metadata)?;
+        self.write_stylesheet(styles)?;
+        self.write_defs(nodes, styles)?;
+        for node in nodes {
+            self.write_node(node)?;
+        }
+        self.end()
+    }
+
+    /// Open the root `<svg>` element, to be closed with [`end`](Self::end), after the XML
+    /// declaration if the options ask for it.
+    ///
+    /// The size of the document is the size of its `view_box`. The `xlink` and `inkscape`
+    /// namespaces are only declared if needed.
+    pub(crate) fn start_svg(
+        &mut self,
+        view_box: Rect,
+        xlink: bool,
+        inkscape: bool,
+    ) -> io::Result<()> {
+        if self.options.xml_declaration {
+            self.xml.declaration()?;
+        }
+        self.xml.start("svg")?;
+        self.xml.attr("width", self.coord(view_box.width()))?;
+        self.xml.attr("height", self.coord(view_box.height()))?;
+        self.write_view_box(view_box)?;
+        self.xml.attr("xmlns", SVG_NS)?;
+        if xlink {
+            self.xml.attr("xmlns:xlink", XLINK_NS)?;
+        }
+        if inkscape {
+            self.xml.attr("xmlns:inkscape", INKSCAPE_NS)?;
+        }
+        Ok(())
+    }
+
+    /// Open a `<g>` element, to be closed with [`end`](Self::end).
+    pub(crate) fn start_group(&mut self, transform: Transform) -> io::Result<()> {
+        self.xml.start("g")?;
+        self.write_transform("transform", transform)
+    }
+
+    /// Write a `<defs>` element with the given paint servers.
+    pub(crate) fn write_paint_servers(&mut self, paints: &[Paint]) -> io::Result<()> {
+        self.xml.start("defs")?;
+        for paint in paints {
+            self.write_paint_server(paint)?;
+        }
+        self.xml.end()
+    }
+
+    /// Close the last opened element.
+    pub(crate) fn end(&mut self) -> io::Result<()> {
+        self.xml.end()
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.xml.finish()
+    }
+
+    fn write_view_box(&mut self, r: Rect) -> io::Result<()> {
+        self.xml.attr(
+            "viewBox",
+            format_args!(
+                "{} {} {} {}",
+                self.coord(r.x()),
+                self.coord(r.y()),
+                self.coord(r.width()),
+                self.coord(r.height())
+            ),
+        )
+    }
+
+    /// Write the accessibility attributes of the `<svg>`, its `<title>` and `<desc>`, and
+    /// the provenance as RDF in a `<metadata>`.
+    fn write_metadata(&mut self, metadata: &Metadata) -> io::Result<()> {
+        if let Some(ref lang) = metadata.lang {
+            self.xml.attr("lang", lang)?;
+            self.xml.attr("xml:lang", lang)?;
+        }
+        if metadata.title.is_some() || metadata.desc.is_some() {
+            self.xml.attr("role", "img")?;
+        }
+        if metadata.title.is_some() {
+            self.xml.attr(
+                "aria-labelledby",
+                format_args!("{}title", self.options.id_prefix),
+            )?;
+        }
+        if metadata.desc.is_some() {
+            self.xml.attr(
+                "aria-describedby",
+                format_args!("{}desc", self.options.id_prefix),
+            )?;
+        }
+        for (name, text) in [("title", &metadata.title), ("desc", &metadata.desc)] {
+            if let Some(text) = text {
+                self.xml.start(name)?;
+                self.write_id(name)?;
+                self.xml.inline_text(text)?;
+                self.xml.end()?;
+            }
+        }
+        if !metadata.has_provenance() {
+            return Ok(());
+        }
+        self.xml.start("metadata")?;
+        self.xml.start("rdf:RDF")?;
+        self.xml.attr("xmlns:rdf", RDF_NS)?;
+        self.xml.attr("xmlns:dc", DC_NS)?;
+        self.xml.attr("xmlns:cc", CC_NS)?;
+        self.xml.start("cc:Work")?;
+        self.xml.attr("rdf:about", "")?;
+        self.xml.start("dc:format")?;
+        self.xml.inline_text("image/svg+xml")?;
+        self.xml.end()?;
+        let terms = [
+            ("dc:title", &metadata.title),
+            ("dc:description", &metadata.desc),
+            ("dc:creator", &metadata.creator),
+            ("dc:date", &metadata.date),
+            ("dc:source", &metadata.source),
+            ("dc:language", &metadata.lang),
+        ];
+        for (name, text) in terms {
+            if let Some(text) = text {
+                self.xml.start(name)?;
+                self.xml.inline_text(text)?;
+                self.xml.end()?;
+            }
+        }
+        if let Some(ref license) = metadata.license {
+            self.xml.start("cc:license")?;
+            self.xml.attr("rdf:resource", license)?;
+            self.xml.end()?;
+        }
+        self.xml.end()?;
+        self.xml.end()?;
+        self.xml.end()
+    }
+
+    /// Write a `<style>` element with a rule for each style, keyed by its class.
+    fn write_stylesheet(&mut self, styles: &[(String, Style)]) -> io::Result<()> {
+        if styles.is_empty() {
+            return Ok(());
+        }
+        self.xml.start("style")?;
+        for (class, style) in styles {
+            let properties: Vec<String> = fill_properties(&style.fill, &self.options)
+                .into_iter()
+                .chain(stroke_properties(&style.stroke, &self.options))
+                .map(|(name, value)| format!("{name}:{value}"))
+                .collect();
+            self.xml
+                .text(&format!(".{class}{{{}}}", properties.join(";")))?;
+        }
+        self.xml.end()
+    }
+
+    fn write_defs(&mut self, nodes: &[Node], styles: &[(String, Style)]) -> io::Result<()> {
+        let mut paints: Vec<Paint> = Vec::new();
+        for node in nodes {
+            collect_paint_servers(node, &mut paints);
+        }
+        for paint in self.symbols.iter().flat_map(path_paints) {
+            add_paint_server(paint, &mut paints);
+        }
+        for (_, style) in styles {
+            let fill = style.fill.iter().map(|f| &f.paint);
+            for paint in fill.chain(style.stroke.iter().map(|s| &s.paint)) {
+                add_paint_server(paint, &mut paints);
+            }
+        }
+        if paints.is_empty() && self.symbols.is_empty() && self.geometries.is_empty() {
+            return Ok(());
+        }
+        self.xml.start("defs")?;
+        for paint in &paints {
+            self.write_paint_server(paint)?;
+        }
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            self.xml.start("symbol")?;
+            self.write_id(format_args!("symbol{i}"))?;
+            self.xml.attr("overflow", "visible")?;
+            self.write_path(symbol, false)?;
+            self.xml.end()?;
+        }
+        // cheap clones of `Arc`s, to write the ids through `self`
+        for (i, data) in self.geometries.clone().iter().enumerate() {
+            self.xml.start("path")?;
+            self.write_id(format_args!("geometry{i}"))?;
+            self.xml.attr("d", self.path_data(data))?;
+            self.xml.end()?;
+        }
+        self.xml.end()
+    }
+
+    fn write_paint_server(&mut self, paint: &Paint) -> io::Result<()> {
+        match paint {
+            Paint::Color(_) => Ok(()),
+            Paint::LinearGradient(lg) => {
+                self.xml.start("linearGradient")?;
+                self.write_id(&lg.id)?;
+                self.xml.attr("x1", self.coord(lg.x1))?;
+                self.xml.attr("y1", self.coord(lg.y1))?;
+                self.xml.attr("x2", self.coord(lg.x2))?;
+                self.xml.attr("y2", self.coord(lg.y2))?;
+                self.write_base_gradient(lg.units, lg.transform, lg.spread_method, &lg.stops)?;
+                self.xml.end()
+            }
+            Paint::RadialGradient(rg) => {
+                self.xml.start("radialGradient")?;
+                self.write_id(&rg.id)?;
+                self.xml.attr("cx", self.coord(rg.cx))?;
+                self.xml.attr("cy", self.coord(rg.cy))?;
+                self.xml.attr("r", self.coord(rg.r))?;
+                self.xml.attr("fx", self.coord(rg.fx))?;
+                self.xml.attr("fy", self.coord(rg.fy))?;
+                self.write_base_gradient(rg.units, rg.transform, rg.spread_method, &rg.stops)?;
+                self.xml.end()
+            }
+            Paint::Pattern(pattern) => {
+                self.xml.start("pattern")?;
+                self.write_id(&pattern.id)?;
+                self.write_rect(pattern.rect)?;
+                self.write_units("patternUnits", pattern.units, Units::ObjectBoundingBox)?;
+                self.write_units(
+                    "patternContentUnits",
+                    pattern.content_units,
+                    Units::UserSpaceOnUse,
+                )?;
+                self.write_transform("patternTransform", pattern.transform)?;
+                for child in &pattern.children {
+                    self.write_node(child)?;
+                }
+                self.xml.end()
+            }
+        }
+    }
+
+    fn write_base_gradient(
+        &mut self,
+        units: Units,
+        transform: Transform,
+        spread_method: SpreadMethod,
+        stops: &[Stop],
+    ) -> io::Result<()> {
+        self.write_units("gradientUnits", units, Units::ObjectBoundingBox)?;
+        self.write_transform("gradientTransform", transform)?;
+        match spread_method {
+            SpreadMethod::Pad => {}
+            SpreadMethod::Reflect => self.xml.attr("spreadMethod", "reflect")?,
+            SpreadMethod::Repeat => self.xml.attr("spreadMethod", "repeat")?,
+        }
+        for stop in stops {
+            self.xml.start("stop")?;
+            self.xml
+                .attr("offset", num(stop.offset.clamp(0.0, 1.0), PRECISION))?;
+            self.xml.attr("stop-color", hex(stop.color))?;
+            self.write_opacity("stop-opacity", stop.opacity)?;
+            self.xml.end()?;
+        }
+        Ok(())
+    }
+
+    /// Write a node, wrapped in an `<a>` if it links somewhere.
+    pub(crate) fn write_node(&mut self, node: &Node) -> io::Result<()> {
+        let Some(ref href) = node.attributes().href else {
+            return self.write_element(node);
+        };
+        self.xml.start("a")?;
+        self.xml.attr("xlink:href", href)?;
+        self.write_element(node)?;
+        self.xml.end()
+    }
+
+    fn write_element(&mut self, node: &Node) -> io::Result<()> {
+        match node {
+            Node::Path(path) => {
+                let ptr = Arc::as_ptr(&path.data);
+                if let Some(&i) = self.symbol_ids.get(&ptr) {
+                    self.write_symbol_use(i, path)
+                } else if let Some(&i) = self.geometry_ids.get(&ptr) {
+                    self.write_geometry_use(i, path)
+                } else {
+                    self.write_path(path, true)
+                }
+            }
+            Node::Image(image) => self.write_image(image),
+            Node::Group(group) => self.write_group(group),
+            // text must be converted to paths before writing
+            Node::Text(_) => Ok(()),
+        }
+    }
+
+    fn write_image(&mut self, image: &ImageNode) -> io::Result<()> {
+        self.xml.start("image")?;
+        self.write_attributes(&image.attributes, None)?;
+        self.write_rect(image.rect)?;
+        match image.rendering_mode {
+            ImageRendering::OptimizeQuality => {}
+            ImageRendering::OptimizeSpeed => self.xml.attr("image-rendering", "optimizeSpeed")?,
+            ImageRendering::Pixelated => self.xml.attr("image-rendering", "pixelated")?,
+        }
+        if image.aspect != AspectRatio::default() {
+            self.xml
+                .attr("preserveAspectRatio", aspect_ratio(image.aspect))?;
+        }
+        self.write_transform("transform", image.transform)?;
+        self.write_image_data(&image.kind)?;
+        self.write_descriptions(&image.attributes)?;
+        self.xml.end()
+    }
+
+    fn write_group(&mut self, group: &GroupNode) -> io::Result<()> {
+        self.xml.start("g")?;
+        self.write_attributes(&group.attributes, None)?;
+        if let Some(ref label) = group.layer {
+            self.xml.attr("inkscape:groupmode", "layer")?;
+            self.xml.attr("inkscape:label", label)?;
+        }
+        self.write_opacity("opacity", group.opacity)?;
+        self.write_transform("transform", group.transform)?;
+        let mut style = Vec::new();
+        if !group.visible {
+            style.push("display:none".to_string());
+        }
+        if group.blend_mode != BlendMode::Normal || group.isolate {
+            style.push(format!(
+                "mix-blend-mode:{};isolation:{}",
+                blend_mode(group.blend_mode),
+                if group.isolate { "isolate" } else { "auto" }
+            ));
+        }
+        if !style.is_empty() {
+            self.xml.attr("style", style.join(";"))?;
+        }
+        self.write_descriptions(&group.attributes)?;
+        for child in &group.children {
+            self.write_node(child)?;
+        }
+        self.xml.end()
+    }
+
+    /// Write the attributes of a node, with the class of its `style` first if it has one.
+    fn write_attributes(
+        &mut self,
+        attributes: &Attributes,
+        style: Option<&String>,
+    ) -> io::Result<()> {
+        if !attributes.id.is_empty() {
+            self.write_id(&attributes.id)?;
+        }
+        let classes: Vec<&String> = style.into_iter().chain(&attributes.classes).collect();
+        if !classes.is_empty() {
+            let classes: Vec<&str> = classes.iter().map(|class| class.as_str()).collect();
+            self.xml.attr("class", classes.join(" "))?;
+        }
+        for (name, value) in &attributes.data {
+            self.xml.attr(&format!("data-{name}"), value)?;
+        }
+        Ok(())
+    }
+
+    /// Write the `<title>` and `<desc>` children of an element, after its attributes.
+    fn write_descriptions(&mut self, attributes: &Attributes) -> io::Result<()> {
+        for (name, text) in [("title", &attributes.title), ("desc", &attributes.desc)] {
+            if let Some(text) = text {
+                self.xml.start(name)?;
+                self.xml.inline_text(text)?;
+                self.xml.end()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_symbol_use(&mut self, symbol: usize, path: &PathNode) -> io::Result<()> {
+        self.xml.start("use")?;
+        self.write_attributes(&path.attributes, path.style.as_ref())?;
+        self.xml.attr(
+            "xlink:href",
+            format_args!("#{}symbol{symbol}", self.options.id_prefix),
+        )?;
+        self.write_transform("transform", path.transform)?;
+        self.write_descriptions(&path.attributes)?;
+        self.xml.end()
+    }
+
+    /// Reference shared path data, the presentation attributes are inherited by the `<path>`.
+    fn write_geometry_use(&mut self, geometry: usize, path: &PathNode) -> io::Result<()> {
+        self.xml.start("use")?;
+        self.write_attributes(&path.attributes, path.style.as_ref())?;
+        self.xml.attr(
+            "xlink:href",
+            format_args!("#{}geometry{geometry}", self.options.id_prefix),
+        )?;
+        self.write_path_style(path)?;
+        self.write_transform("transform", path.transform)?;
+        self.write_descriptions(&path.attributes)?;
+        self.xml.end()
+    }
+
+    pub(crate) fn write_path(&mut self, path: &PathNode, with_transform: bool) -> io::Result<()> {
+        self.xml.start("path")?;
+        self.write_attributes(&path.attributes, path.style.as_ref())?;
+        self.write_path_style(path)?;
+        if with_transform {
+            self.write_transform("transform", path.transform)?;
+        }
+        self.xml.attr("d", self.path_data(&path.data))?;
+        self.write_descriptions(&path.attributes)?;
+        self.xml.end()
+    }
+
+    fn write_path_style(&mut self, path: &PathNode) -> io::Result<()> {
+        if path.style.is_some() {
+            // the stylesheet takes care of it
+            return Ok(());
+        }
+        self.write_fill(&path.fill)?;
+        self.write_stroke(&path.stroke)
+    }
+
+    fn write_fill(&mut self, fill: &Option<Fill>) -> io::Result<()> {
+        for (name, value) in fill_properties(fill, &self.options) {
+            self.xml.attr(name, value)?;
+        }
+        Ok(())
+    }
+
+    fn write_stroke(&mut self, stroke: &Option<Stroke>) -> io::Result<()> {
+        for (name, value) in stroke_properties(stroke, &self.options) {
+            self.xml.attr(name, value)?;
+        }
+        Ok(())
+    }
+
+    fn write_image_data(&mut self, kind: &ImageKind) -> io::Result<()> {
+        self.xml.attr("xlink:href", image_data_uri(kind))
+    }
+
+    fn write_rect(&mut self, rect: Rect) -> io::Result<()> {
+        self.xml.attr("x", self.coord(rect.x()))?;
+        self.xml.attr("y", self.coord(rect.y()))?;
+        self.xml.attr("width", self.coord(rect.width()))?;
+        self.xml.attr("height", self.coord(rect.height()))
+    }
+
+    fn write_units(&mut self, name: &str, units: Units, default: Units) -> io::Result<()> {
+        match units {
+            _ if units == default => Ok(()),
+            Units::UserSpaceOnUse => self.xml.attr(name, "userSpaceOnUse"),
+            Units::ObjectBoundingBox => self.xml.attr(name, "objectBoundingBox"),
+        }
+    }
+
+    /// Write an opacity clamped to `[0, 1]`, unless it is fully opaque.
+    fn write_opacity(&mut self, name: &str, opacity: f32) -> io::Result<()> {
+        if opacity >= 1.0 {
+            return Ok(());
+        }
+        self.xml.attr(name, num(opacity.max(0.0), PRECISION))
+    }
+
+    fn write_transform(&mut self, name: &str, ts: Transform) -> io::Result<()> {
+        if ts.is_identity() {
+            return Ok(());
+        }
+        let precision = self.options.transforms_precision;
+        self.xml.attr(
+            name,
+            format_args!(
+                "matrix({} {} {} {} {} {})",
+                num(ts.sx, precision),
+                num(ts.ky, precision),
+                num(ts.kx, precision),
+                num(ts.sy, precision),
+                num(ts.tx, precision),
+                num(ts.ty, precision)
+            ),
+        )
+    }
+
+    fn write_id(&mut self, id: impl Display) -> io::Result<()> {
+        self.xml
+            .attr("id", format_args!("{}{id}", self.options.id_prefix))
+    }
+
+    fn coord(&self, n: f32) -> impl Display {
+        num(n, self.options.coordinates_precision)
+    }
+
+    fn path_data(&self, data: &PathData) -> String {
+        path_data(
+            data,
+            self.options.coordinates_precision,
+            self.options.relative_paths,
+        )
+    }
+}
+
+/// Whether images or links, which reference with `xlink:href`, are among the nodes.
+fn has_xlinks(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match node {
+        _ if node.attributes().href.is_some() => true,
+        Node::Image(_) => true,
+        Node::Group(group) => has_xlinks(&group.children),
+        Node::Path(_) | Node::Text(_) => false,
+    })
+}
+
+fn has_layers(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Group(group) => group.layer.is_some() || has_layers(&group.children),
+        Node::Path(_) | Node::Image(_) | Node::Text(_) => false,
+    })
+}
+
+/// Gather the paint servers used by `node` and its descendants, without repeating ids.
+fn collect_paint_servers(node: &Node, paints: &mut Vec<Paint>) {
+    match node {
+        Node::Path(path) => {
+            for paint in path_paints(path) {
+                add_paint_server(paint, paints);
+            }
+        }
+        Node::Group(group) => {
+            for child in &group.children {
+                collect_paint_servers(child, paints);
+            }
+        }
+        Node::Image(_) | Node::Text(_) => {}
+    }
+}
+
+/// Add `paint` to `paints` if it is a paint server not there yet.
+///
+/// The paint servers used by the children of a pattern come before it.
+pub(crate) fn add_paint_server(paint: &Paint, paints: &mut Vec<Paint>) {
+    if let Some(id) = paint_id(paint) {
+        if !paints.iter().any(|p| paint_id(p) == Some(id)) {
+            if let Paint::Pattern(pattern) = paint {
+                for child in &pattern.children {
+                    collect_paint_servers(child, paints);
+                }
+            }
+            paints.push(paint.clone());
+        }
+    }
+}
+
+/// Id of the paint server of `paint`, if it is not a plain color.
+pub(crate) fn paint_id(paint: &Paint) -> Option<&String> {
+    match paint {
+        Paint::Color(_) => None,
+        Paint::LinearGradient(lg) => Some(&lg.id),
+        Paint::RadialGradient(rg) => Some(&rg.id),
+        Paint::Pattern(pattern) => Some(&pattern.id),
+    }
+}
+
+pub(crate) fn path_paints(path: &PathNode) -> impl Iterator<Item = &Paint> {
+    path.fill
+        .iter()
+        .map(|f| &f.paint)
+        .chain(path.stroke.iter().map(|s| &s.paint))
+}
+
+/// The presentation attributes of a fill, as pairs of name and value.
+///
+/// They are the same as CSS properties, so they are used for the stylesheet too.
+fn fill_properties(fill: &Option<Fill>, options: &WriteOptions) -> Vec<(&'static str, String)> {
+    let Some(fill) = fill else {
+        return vec![("fill", "none".to_string())];
+    };
+    let mut properties = vec![("fill", paint_value(&fill.paint, &options.id_prefix))];
+    if fill.opacity < 1.0 {
+        properties.push((
+            "fill-opacity",
+            num(fill.opacity.max(0.0), PRECISION).to_string(),
+        ));
+    }
+    if fill.rule == FillRule::EvenOdd {
+        properties.push(("fill-rule", "evenodd".to_string()));
+    }
+    properties
+}
+
+/// The presentation attributes of a stroke, as pairs of name and value.
+fn stroke_properties(
+    stroke: &Option<Stroke>,
+    options: &WriteOptions,
+) -> Vec<(&'static str, String)> {
+    let precision = options.coordinates_precision;
+    let Some(stroke) = stroke else {
+        return vec![("stroke", "none".to_string())];
+    };
+    let mut properties = vec![("stroke", paint_value(&stroke.paint, &options.id_prefix))];
+    if stroke.opacity < 1.0 {
+        properties.push((
+            "stroke-opacity",
+            num(stroke.opacity.max(0.0), PRECISION).to_string(),
+        ));
+    }
+    if stroke.dashoffset != 0.0 {
+        properties.push((
+            "stroke-dashoffset",
+            num(stroke.dashoffset, precision).to_string(),
+        ));
+    }
+    if stroke.miterlimit != 4.0 {
+        properties.push((
+            "stroke-miterlimit",
+            num(stroke.miterlimit.max(1.0), PRECISION).to_string(),
+        ));
+    }
+    if stroke.width != 1.0 {
+        properties.push(("stroke-width", num(stroke.width, precision).to_string()));
+    }
+    match stroke.linecap {
+        LineCap::Butt => {}
+        LineCap::Round => properties.push(("stroke-linecap", "round".to_string())),
+        LineCap::Square => properties.push(("stroke-linecap", "square".to_string())),
+    }
+    match stroke.linejoin {
+        LineJoin::Miter => {}
+        LineJoin::MiterClip => properties.push(("stroke-linejoin", "miter-clip".to_string())),
+        LineJoin::Round => properties.push(("stroke-linejoin", "round".to_string())),
+        LineJoin::Bevel => properties.push(("stroke-linejoin", "bevel".to_string())),
+    }
+    if let Some(ref dashes) = stroke.dasharray {
+        let dashes: Vec<String> = dashes
+            .iter()
+            .map(|d| num(*d, precision).to_string())
+            .collect();
+        properties.push(("stroke-dasharray", dashes.join(" ")));
+    }
+    properties
+}
+
+fn paint_value(paint: &Paint, id_prefix: &str) -> String {
+    match paint {
+        Paint::Color(color) => hex(*color),
+        Paint::LinearGradient(lg) => format!("url(#{id_prefix}{})", lg.id),
+        Paint::RadialGradient(rg) => format!("url(#{id_prefix}{})", rg.id),
+        Paint::Pattern(pattern) => format!("url(#{id_prefix}{})", pattern.id),
+    }
+}
+
+/// Write the `d` attribute of a path, with coordinates rounded to `precision` decimals.
+///
+/// Relative commands are computed from the rounded points, so that rounding errors do
+/// not add up along the path.
+fn path_data(data: &PathData, precision: u8, relative: bool) -> String {
+    let round = |p: Point| Point::from_xy(round(p.x, precision), round(p.y, precision));
+    let mut d = String::new();
+    let (mut current, mut start) = (Point::zero(), Point::zero());
+    for segment in data.segments() {
+        let (command, points) = match segment {
+            PathSegment::MoveTo(p) => ('M', vec![p]),
+            PathSegment::LineTo(p) => ('L', vec![p]),
+            PathSegment::QuadTo(p1, p) => ('Q', vec![p1, p]),
+            PathSegment::CubicTo(p1, p2, p) => ('C', vec![p1, p2, p]),
+            PathSegment::Close => ('Z', vec![]),
+        };
+        if !d.is_empty() {
+            d.push(' ');
+        }
+        d.push(if relative {
+            command.to_ascii_lowercase()
+        } else {
+            command
+        });
+        let points: Vec<Point> = points.into_iter().map(round).collect();
+        for p in &points {
+            let p = if relative { *p - current } else { *p };
+            d.push_str(&format!(" {} {}", num(p.x, precision), num(p.y, precision)));
+        }
+        match (command, points.last()) {
+            ('M', Some(&p)) => (current, start) = (p, p),
+            ('Z', _) => current = start,
+            (_, Some(&p)) => current = p,
+            _ => {}
+        }
+    }
+    d
+}
+
+/// Format a number rounded to `precision` decimals, avoiding outputs like
+/// `29.999999999999996`.
+fn num(n: f32, precision: u8) -> impl Display {
+    debug_assert!(
+        n.is_finite(),
+        "non-finite numbers are rejected before writing"
+    );
+    if n.fract().approx_zero_ulps(4) {
+        // `f64` holds any integral `f32` exactly, and prints it without an exponent
+        let integral = n.trunc() as f64;
+        return if integral == 0.0 {
+            "0".to_string()
+        } else {
+            integral.to_string()
+        };
+    }
+    let rounded = round(n, precision);
+    if rounded == 0.0 {
+        // no `-0`
+        return "0".to_string();
+    }
+    rounded.to_string()
+}
+
+/// Round `n` to `precision` decimals.
+///
+/// The powers of ten are exact constants instead of computed with `powi`, whose result may
+/// depend on the platform, so that the output is the same everywhere.
+fn round(n: f32, precision: u8) -> f32 {
+    const POWERS_OF_TEN: [f64; 16] = [
+        1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15,
+    ];
+    let pow = POWERS_OF_TEN[(precision as usize).min(POWERS_OF_TEN.len() - 1)];
+    ((n as f64 * pow).round() / pow) as f32
+}
+
+/// The encoded image as a `data:` URI.
+pub(crate) fn image_data_uri(kind: &ImageKind) -> String {
+    use base64::Engine;
+
+    format!(
+        "data:{};base64,{}",
+        kind.format().mime_type(),
+        base64::engine::general_purpose::STANDARD.encode(kind.data().as_slice())
+    )
+}
+
+fn aspect_ratio(aspect: AspectRatio) -> String {
+    let align = match aspect.align {
+        Align::None => "none",
+        Align::XMinYMin => "xMinYMin",
+        Align::XMidYMin => "xMidYMin",
+        Align::XMaxYMin => "xMaxYMin",
+        Align::XMinYMid => "xMinYMid",
+        Align::XMidYMid => "xMidYMid",
+        Align::XMaxYMid => "xMaxYMid",
+        Align::XMinYMax => "xMinYMax",
+        Align::XMidYMax => "xMidYMax",
+        Align::XMaxYMax => "xMaxYMax",
+    };
+    if aspect.slice {
+        format!("{align} slice")
+    } else {
+        align.to_string()
+    }
+}
+
+pub(crate) fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+pub(crate) fn blend_mode(mode: BlendMode) -> &'static str {
+    match mode {
+        BlendMode::Normal => "normal",
+        BlendMode::Multiply => "multiply",
+        BlendMode::Screen => "screen",
+        BlendMode::Overlay => "overlay",
+        BlendMode::Darken => "darken",
+        BlendMode::Lighten => "lighten",
+        BlendMode::ColorDodge => "color-dodge",
+        BlendMode::ColorBurn => "color-burn",
+        BlendMode::HardLight => "hard-light",
+        BlendMode::SoftLight => "soft-light",
+        BlendMode::Difference => "difference",
+        BlendMode::Exclusion => "exclusion",
+        BlendMode::Hue => "hue",
+        BlendMode::Saturation => "saturation",
+        BlendMode::Color => "color",
+        BlendMode::Luminosity => "luminosity",
+    }
+}
+
+/// Minimal XML writer, indenting and quoting as set by [`WriteOptions::indent`] and
+/// [`WriteOptions::quotes`].
+pub(crate) struct XmlWriter<W: Write> {
+    out: W,
+    open: Vec<&'static str>,
+    /// Whether the last started element still lacks its closing `>`.
+    in_tag: bool,
+    /// Whether the last started element holds inline text, to close it on the same line.
+    inline: bool,
+    indent: Indent,
+    quote: char,
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub(crate) fn new(out: W, indent: Indent, quotes: AttributeQuotes) -> Self {
+        Self {
+            out,
+            open: Vec::new(),
+            in_tag: false,
+            inline: false,
+            indent,
+            quote: match quotes {
+                AttributeQuotes::Double => '"',
+                AttributeQuotes::Single => '\'',
+            },
+        }
+    }
+
+    pub(crate) fn declaration(&mut self) -> io::Result<()> {
+        debug_assert!(self.open.is_empty(), "the declaration must come first");
+        let quote = self.quote;
+        write!(
+            self.out,
+            "<?xml version={quote}1.0{quote} encoding={quote}UTF-8{quote}?>"
+        )?;
+        self.newline(0)
+    }
+
+    pub(crate) fn start(&mut self, name: &'static str) -> io::Result<()> {
+        if self.in_tag {
+            self.out.write_all(b">")?;
+        }
+        if !self.open.is_empty() {
+            self.newline(self.open.len())?;
+        }
+        write!(self.out, "<{name}")?;
+        self.open.push(name);
+        self.in_tag = true;
+        Ok(())
+    }
+
+    pub(crate) fn attr(&mut self, name: &str, value: impl Display) -> io::Result<()> {
+        debug_assert!(self.in_tag, "attributes must follow a start tag");
+        let quote = self.quote;
+        write!(self.out, " {name}={quote}")?;
+        escape(&mut self.out, &value.to_string(), quote)?;
+        write!(self.out, "{quote}")
+    }
+
+    /// Write escaped text content in the current element, on its own line.
+    pub(crate) fn text(&mut self, text: &str) -> io::Result<()> {
+        if self.in_tag {
+            self.in_tag = false;
+            self.out.write_all(b">")?;
+        }
+        self.newline(self.open.len())?;
+        escape(&mut self.out, text, '"')
+    }
+
+    /// Write escaped text content in the current element, closed right after it.
+    pub(crate) fn inline_text(&mut self, text: &str) -> io::Result<()> {
+        if self.in_tag {
+            self.in_tag = false;
+            self.out.write_all(b">")?;
+        }
+        self.inline = true;
+        escape(&mut self.out, text, '"')
+    }
+
+    pub(crate) fn end(&mut self) -> io::Result<()> {
+        let name = self.open.pop().expect("end() without an open element");
+        if self.in_tag {
+            self.in_tag = false;
+            return self.out.write_all(b"/>");
+        }
+        if self.inline {
+            self.inline = false;
+            return write!(self.out, "</{name}>");
+        }
+        self.newline(self.open.len())?;
+        write!(self.out, "</{name}>")
+    }
+
+    /// Return the underlying writer.
+    pub(crate) fn finish(self) -> W {
+        debug_assert!(self.open.is_empty(), "unclosed elements");
+        self.out
+    }
+
+    fn newline(&mut self, depth: usize) -> io::Result<()> {
+        let unit = match self.indent {
+            Indent::None => return Ok(()),
+            Indent::Spaces(n) => " ".repeat(n as usize),
+            Indent::Tabs => "\t".to_string(),
+        };
+        self.out.write_all(b"\n")?;
+        for _ in 0..depth {
+            self.out.write_all(unit.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Escape the markup characters of `text`, and the `quote` that delimits it.
+fn escape<W: Write>(out: &mut W, text: &str, quote: char) -> io::Result<()> {
+    for c in text.chars() {
+        match c {
+            '&' => out.write_all(b"&amp;")?,
+            '<' => out.write_all(b"&lt;")?,
+            '>' => out.write_all(b"&gt;")?,
+            '"' if quote == '"' => out.write_all(b"&quot;")?,
+            '\'' if quote == '\'' => out.write_all(b"&apos;")?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    Ok(())
+}